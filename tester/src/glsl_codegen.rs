@@ -16,37 +16,109 @@ layout (local_size_x = 16) in;
 uint gid = gl_GlobalInvocationID.x;
 ";
 
+/// The layout rule BINDS declares the collection with
+pub const LAYOUT_RULE: LayoutRule = LayoutRule::Std140;
+
 const BINDS: &str = "
 layout(std140, binding = 0) buffer Collection {
     TestStruct data[];
 };
 ";
 
-fn glsl_typename(ty: &AbstractType) -> &str {
+/// Whether a field must be declared with layout(row_major)
+fn is_row_major(ty: &AbstractType) -> bool {
     match ty {
-        AbstractType::Float => "float",
-        AbstractType::Vec2 => "vec2",
-        AbstractType::Vec3 => "vec3",
-        AbstractType::Vec4 => "vec4",
+        AbstractType::Matrix(m) => m.row_major,
+        AbstractType::Array(elem, _) => is_row_major(elem),
+        _ => false,
     }
 }
 
-pub fn make_test(fields: &[AbstractField]) -> Result<String> {
-    let mut output = String::new();
-    // Prelude
-    output.push_str(PRELUDE);
+/// A statement which changes the field in a way dependent on gid, so each element is distinguishable
+fn test_pattern(access: &str, ty: &AbstractType, depth: usize) -> String {
+    // Arrays can't be operated on as a whole, so loop over every element
+    if let AbstractType::Array(elem, len) = ty {
+        let index = format!("i{}", depth);
+        let elem_access = format!("{}[{}]", access, index);
+        return format!(
+            "for (int {i} = 0; {i} < {len}; {i}++) {{ {body} }}",
+            i = index,
+            len = len,
+            body = test_pattern(&elem_access, elem, depth + 1),
+        );
+    }
 
-    // Structure
-    output.push_str("struct TestStruct {\n");
+    // Structs are changed field by field
+    if let AbstractType::Struct(s) = ty {
+        let patterns: Vec<String> = s
+            .fields
+            .iter()
+            .map(|f| test_pattern(&format!("{}.{}", access, f.name), &f.ty, depth))
+            .collect();
+        return patterns.join(" ");
+    }
+
+    match ty.component_type() {
+        AbstractType::Float => format!("{} *= float(gid);", access),
+        AbstractType::Int => format!("{} *= int(gid);", access),
+        AbstractType::UInt => format!("{} *= gid;", access),
+        AbstractType::Double => format!("{} *= double(gid);", access),
+        AbstractType::Bool if ty.components() == 1 => format!("{} = !{};", access, access),
+        AbstractType::Bool => format!("{} = not({});", access, access),
+        _ => unreachable!("Component types are always scalars"),
+    }
+}
+
+/// Collects every struct used by a type, with dependencies ahead of the structs using them
+fn collect_structs(ty: &AbstractType, structs: &mut Vec<AbstractStruct>) {
+    match ty {
+        AbstractType::Array(elem, _) => collect_structs(elem, structs),
+        AbstractType::Struct(s) => {
+            for field in &s.fields {
+                collect_structs(&field.ty, structs);
+            }
+            if !structs.iter().any(|other| other.name == s.name) {
+                structs.push(s.clone());
+            }
+        }
+        _ => (),
+    }
+}
+
+fn write_struct(output: &mut String, name: &str, fields: &[AbstractField]) -> Result<()> {
+    write!(output, "struct {} {{\n", name)?;
     for field in fields {
+        let qualifier = if is_row_major(&field.ty) {
+            "layout(row_major) "
+        } else {
+            ""
+        };
         write!(
-            &mut output,
-            "    {} {};\n",
-            glsl_typename(&field.ty),
+            output,
+            "    {}{} {};\n",
+            qualifier,
+            field.ty,
             field.name,
         )?;
     }
     output.push_str("};\n");
+    Ok(())
+}
+
+pub fn make_test(fields: &[AbstractField]) -> Result<String> {
+    let mut output = String::new();
+    // Prelude
+    output.push_str(PRELUDE);
+
+    // Structures
+    let mut structs = Vec::new();
+    for field in fields {
+        collect_structs(&field.ty, &mut structs);
+    }
+    for s in &structs {
+        write_struct(&mut output, &s.name, &s.fields)?;
+    }
+    write_struct(&mut output, "TestStruct", fields)?;
 
     // Bindings
     output.push_str(BINDS);
@@ -54,7 +126,8 @@ pub fn make_test(fields: &[AbstractField]) -> Result<String> {
     // Test pattern
     output.push_str("void main() {\n");
     for field in fields {
-        write!(&mut output, "    data[gid].{} *= float(gid);\n", field.name)?;
+        let access = format!("data[gid].{}", field.name);
+        write!(&mut output, "    {}\n", test_pattern(&access, &field.ty, 0))?;
     }
     output.push_str("}\n");
 
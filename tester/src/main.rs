@@ -11,17 +11,11 @@ use shader_executor::ShaderExecutor;
 
 fn main() -> Result<()> {
     let fields = [
-        AbstractField {
-            name: "wonk".into(),
-            ty: AbstractType::Vec2,
-        },
-        AbstractField {
-            name: "clonk".into(),
-            ty: AbstractType::Float,
-        },
+        AbstractField::new("wonk", AbstractType::Vec2),
+        AbstractField::new("clonk", AbstractType::Float),
     ];
 
-    summarize_layout(&naive_layout_glsl_only(&fields));
+    summarize_layout(&naive_layout_glsl_only(&fields, glsl_codegen::LAYOUT_RULE));
 
     const INVOCATIONS: u32 = 1;
     let mut test = TestCase::new(&fields, INVOCATIONS, 0)?;
@@ -27,6 +27,66 @@ fn add_contiguous_floats(
     }
 }
 
+fn add_contiguous_doubles(
+    initial: &mut Vec<u8>,
+    expected: &mut Vec<u8>,
+    count: usize,
+    gid: u32,
+    rng: &mut impl Rng,
+) {
+    let vi: f64 = rng.gen_range(-100.0, 100.0);
+    let ve = vi * gid as f64;
+    for _ in 0..count {
+        initial.extend_from_slice(&vi.to_le_bytes()[..]);
+        expected.extend_from_slice(&ve.to_le_bytes()[..]);
+    }
+}
+
+fn add_contiguous_ints(
+    initial: &mut Vec<u8>,
+    expected: &mut Vec<u8>,
+    count: usize,
+    gid: u32,
+    rng: &mut impl Rng,
+) {
+    let vi: i32 = rng.gen_range(-100, 100);
+    let ve = vi.wrapping_mul(gid as i32);
+    for _ in 0..count {
+        initial.extend_from_slice(&vi.to_le_bytes()[..]);
+        expected.extend_from_slice(&ve.to_le_bytes()[..]);
+    }
+}
+
+fn add_contiguous_uints(
+    initial: &mut Vec<u8>,
+    expected: &mut Vec<u8>,
+    count: usize,
+    gid: u32,
+    rng: &mut impl Rng,
+) {
+    let vi: u32 = rng.gen_range(0, 100);
+    let ve = vi.wrapping_mul(gid);
+    for _ in 0..count {
+        initial.extend_from_slice(&vi.to_le_bytes()[..]);
+        expected.extend_from_slice(&ve.to_le_bytes()[..]);
+    }
+}
+
+fn add_contiguous_bools(
+    initial: &mut Vec<u8>,
+    expected: &mut Vec<u8>,
+    count: usize,
+    rng: &mut impl Rng,
+) {
+    // Bools are stored as 32 bit words, and the test pattern inverts them
+    let vi: bool = rng.gen();
+    let ve = !vi;
+    for _ in 0..count {
+        initial.extend_from_slice(&(vi as u32).to_le_bytes()[..]);
+        expected.extend_from_slice(&(ve as u32).to_le_bytes()[..]);
+    }
+}
+
 fn add_test_value(
     ty: &AbstractType,
     initial: &mut Vec<u8>,
@@ -34,11 +94,60 @@ fn add_test_value(
     gid: u32,
     rng: &mut impl Rng,
 ) {
-    match ty {
-        AbstractType::Float => add_contiguous_floats(initial, expected, 1, gid, rng),
-        AbstractType::Vec2 => add_contiguous_floats(initial, expected, 2, gid, rng),
-        AbstractType::Vec3 => add_contiguous_floats(initial, expected, 3, gid, rng),
-        AbstractType::Vec4 => add_contiguous_floats(initial, expected, 4, gid, rng),
+    if let AbstractType::Struct(s) = ty {
+        add_struct_values(&s.fields, initial, expected, gid, rng);
+        return;
+    }
+
+    // Array elements are each padded out to the array stride
+    if let AbstractType::Array(elem, len) = ty {
+        let padding = elem.array_stride_gl(LAYOUT_RULE) - elem.size_gl(LAYOUT_RULE);
+        for _ in 0..*len {
+            add_test_value(elem, initial, expected, gid, rng);
+            initial.extend((0..padding).map(|_| 0));
+            expected.extend((0..padding).map(|_| 0));
+        }
+        return;
+    }
+
+    // Matrices are stored as an array of vectors, each padded out to the array stride
+    if let AbstractType::Matrix(m) = ty {
+        let vector = m.vector();
+        let padding = vector.array_stride_gl(LAYOUT_RULE) - vector.size();
+        for _ in 0..m.vector_count() {
+            add_test_value(&vector, initial, expected, gid, rng);
+            initial.extend((0..padding).map(|_| 0));
+            expected.extend((0..padding).map(|_| 0));
+        }
+        return;
+    }
+
+    let count = ty.components() as usize;
+    match ty.component_type() {
+        AbstractType::Float => add_contiguous_floats(initial, expected, count, gid, rng),
+        AbstractType::Int => add_contiguous_ints(initial, expected, count, gid, rng),
+        AbstractType::UInt => add_contiguous_uints(initial, expected, count, gid, rng),
+        AbstractType::Bool => add_contiguous_bools(initial, expected, count, rng),
+        AbstractType::Double => add_contiguous_doubles(initial, expected, count, gid, rng),
+        _ => unreachable!("Component types are always scalars"),
+    }
+}
+
+fn add_struct_values(
+    fields: &[AbstractField],
+    initial: &mut Vec<u8>,
+    expected: &mut Vec<u8>,
+    gid: u32,
+    rng: &mut impl Rng,
+) {
+    for fg in &naive_layout_glsl_only(fields, LAYOUT_RULE) {
+        match fg {
+            FieldGap::Gap(g, _) => {
+                initial.extend((0..*g).map(|_| 0));
+                expected.extend((0..*g).map(|_| 0));
+            }
+            FieldGap::Field(f, _) => add_test_value(&f.ty, initial, expected, gid, rng),
+        }
     }
 }
 
@@ -46,22 +155,11 @@ impl TestCase {
     pub fn new(fields: &[AbstractField], invocations: u32, seed: u64) -> Result<Self> {
         let mut initial = Vec::new();
         let mut expected = Vec::new();
-        let naive_layout = naive_layout_glsl_only(fields);
 
         let mut rng = SmallRng::seed_from_u64(seed);
 
         for gid in 0..invocations * LOCAL_SIZE {
-            for fg in &naive_layout {
-                match fg {
-                    FieldGap::Gap(g) => {
-                        initial.extend((0..*g).map(|_| 0));
-                        expected.extend((0..*g).map(|_| 0));
-                    }
-                    FieldGap::Field(f) => {
-                        add_test_value(&f.ty, &mut initial, &mut expected, gid, &mut rng)
-                    }
-                }
-            }
+            add_struct_values(fields, &mut initial, &mut expected, gid, &mut rng);
         }
 
         let glsl_code = make_test(fields)?;
@@ -1,69 +1,776 @@
+use crate::c_layout::{naive_layout_c, struct_align_c};
+use crate::glsl_layout::{
+    layout_size, naive_layout_glsl_only, round_up, struct_align_gl, LayoutRule,
+};
+use crate::diagnostics::DeclarationKind;
 use crate::Result;
-use glsl::syntax::{StructFieldSpecifier, TypeSpecifierNonArray};
-use std::convert::{TryFrom, TryInto};
+use glsl::syntax::{
+    ArraySpecifier, ArraySpecifierDimension, ArrayedIdentifier, BinaryOp, Expr,
+    LayoutQualifierSpec, StructFieldSpecifier, StructSpecifier, TypeName, TypeQualifier,
+    TypeQualifierSpec, TypeSpecifierNonArray, UnaryOp,
+};
+use glsl::transpiler::glsl::show_type_specifier_non_array;
+use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AbstractType {
     Float,
     Vec2,
     Vec3,
     Vec4,
+    Int,
+    IVec2,
+    IVec3,
+    IVec4,
+    UInt,
+    UVec2,
+    UVec3,
+    UVec4,
+    Bool,
+    BVec2,
+    BVec3,
+    BVec4,
+    Double,
+    DVec2,
+    DVec3,
+    DVec4,
+    Matrix(Matrix),
+    /// A fixed-size array of the given element type and length
+    Array(Box<AbstractType>, u64),
+    /// An array whose length is only known at runtime, only valid as the last member of a buffer block
+    RuntimeArray(Box<AbstractType>),
+    /// A user-defined struct
+    Struct(AbstractStruct),
 }
 
-#[derive(Clone, Debug)]
+/// A float or double matrix, laid out as an array of column vectors (or row vectors if row major)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Matrix {
+    pub columns: u64,
+    pub rows: u64,
+    pub double: bool,
+    pub row_major: bool,
+}
+
+/// A named struct, which is mirrored by a Rust struct of the same name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbstractStruct {
+    pub name: String,
+    pub fields: Vec<AbstractField>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AbstractField {
     pub name: String,
     pub ty: AbstractType,
+    /// Offset given by a `layout(offset = N)` qualifier, only valid on block members
+    pub offset: Option<u64>,
+    /// Minimum alignment given by a `layout(align = N)` qualifier, on the member or its block
+    pub align: Option<u64>,
+    /// Majority given by a `row_major` or `column_major` qualifier on the member itself,
+    /// which the default of an enclosing block doesn't override
+    pub row_major: Option<bool>,
+}
+
+/// The problems found with one field, in the order they were found
+#[derive(Debug)]
+pub struct FieldErrors {
+    pub field: String,
+    pub errors: Vec<crate::Error>,
+}
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "field {}:", self.field)?;
+        for error in &self.errors {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The fields which extracted cleanly from a list of field specifiers, and the problems with the rest
+#[derive(Debug, Default)]
+pub struct FieldReport {
+    pub fields: Vec<AbstractField>,
+    /// Problems grouped by the field they're in, in the order the fields were declared
+    pub errors: Vec<FieldErrors>,
+}
+
+impl FieldReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Adds a problem to the group for `field`, starting one if it's the field's first
+    pub(crate) fn push_error(&mut self, field: &str, error: crate::Error) {
+        match self.errors.iter_mut().find(|group| group.field == field) {
+            Some(group) => group.errors.push(error),
+            None => self.errors.push(FieldErrors {
+                field: field.into(),
+                errors: vec![error],
+            }),
+        }
+    }
+
+    /// Removes the fields failing a check, adding the problem with each to the report
+    fn reject<F>(&mut self, mut check: F)
+    where
+        F: FnMut(&AbstractField) -> Option<crate::Error>,
+    {
+        let mut fields = Vec::new();
+        for field in std::mem::take(&mut self.fields) {
+            match check(&field) {
+                Some(error) => self.push_error(&field.name, error),
+                None => fields.push(field),
+            }
+        }
+        self.fields = fields;
+    }
+
+    /// The extracted fields, or else the first problem in the context of its field
+    pub fn into_result(self) -> Result<Vec<AbstractField>> {
+        let group = match self.errors.into_iter().next() {
+            Some(group) => group,
+            None => return Ok(self.fields),
+        };
+        let field = group.field;
+        match group.errors.into_iter().next() {
+            Some(error) => Err(error.in_declaration(DeclarationKind::Field, &field)),
+            None => Ok(self.fields),
+        }
+    }
+}
+
+impl AbstractStruct {
+    /// Converts a struct definition, resolving the names of any struct typed fields against `structs`
+    pub fn from_specifier(spec: &StructSpecifier, structs: &[AbstractStruct]) -> Result<Self> {
+        let name = match &spec.name {
+            Some(name) => name.0.clone(),
+            None => return Err(crate::Error::AnonymousStruct),
+        };
+        let fields = Self::report_specifier(spec, structs)
+            .into_result()
+            .map_err(|e| e.in_declaration(DeclarationKind::Struct, &name))?;
+        Ok(Self { name, fields })
+    }
+
+    /// Like `from_specifier`, but finds every problem with the fields instead of stopping at the first
+    pub fn report_specifier(spec: &StructSpecifier, structs: &[AbstractStruct]) -> FieldReport {
+        let mut report = AbstractField::report_all(&spec.fields.0, structs);
+
+        // Explicit offsets and alignments are only allowed on block members
+        report.reject(|f| {
+            if f.offset.is_some() || f.align.is_some() {
+                Some(crate::Error::ExplicitLayoutOutsideBlock {
+                    name: f.name.clone(),
+                })
+            } else {
+                None
+            }
+        });
+
+        // Only buffer blocks may end in a runtime-sized array
+        report.reject(|f| {
+            if f.ty.is_runtime_array() {
+                Some(crate::Error::MisplacedRuntimeArray {
+                    name: f.name.clone(),
+                })
+            } else {
+                None
+            }
+        });
+
+        report
+    }
 }
 
 impl AbstractField {
+    /// A field without any explicit offset or alignment
+    pub fn new<S: Into<String>>(name: S, ty: AbstractType) -> Self {
+        Self {
+            name: name.into(),
+            ty,
+            offset: None,
+            align: None,
+            row_major: None,
+        }
+    }
+
+    /// Alignment of the field under a GLSL rule, including any `align` qualifier
+    pub fn align_gl(&self, rule: LayoutRule) -> u64 {
+        self.ty.align_gl(rule).max(self.align.unwrap_or(1))
+    }
+
+    /// Extracts every field declared by a list of field specifiers, in order
+    pub fn extract_all(
+        fields: &[StructFieldSpecifier],
+        structs: &[AbstractStruct],
+    ) -> Result<Vec<Self>> {
+        Self::report_all(fields, structs).into_result()
+    }
+
+    /// Like `extract_all`, but carries on past bad fields to find every problem at once
+    pub fn report_all(fields: &[StructFieldSpecifier], structs: &[AbstractStruct]) -> FieldReport {
+        let mut report = FieldReport::default();
+        for field in fields {
+            Self::report_fields(field, structs, &mut report);
+        }
+
+        let last = report.fields.last().map(|f| f.name.clone());
+        report.reject(|f| {
+            if f.ty.is_runtime_array() && Some(&f.name) != last.as_ref() {
+                Some(crate::Error::MisplacedRuntimeArray {
+                    name: f.name.clone(),
+                })
+            } else {
+                None
+            }
+        });
+
+        report
+    }
+
+    /// Extracts the fields declared by one field specifier, resolving struct types against `structs`
     pub fn extract_fields<'a>(
         field: &'a StructFieldSpecifier,
+        structs: &[AbstractStruct],
     ) -> Result<impl Iterator<Item = Result<Self>> + 'a> {
-        if field.qualifier.is_some() {
-            return Err(crate::Error::QualifiersUnsupported);
+        // Errors about the shared type and qualifiers are reported against the first identifier
+        let first = &field.identifiers.0[0].ident.0;
+        let in_field = |e: crate::Error| e.in_declaration(DeclarationKind::Field, first);
+
+        let mut ty = Self::resolve_type(field, structs).map_err(in_field)?;
+        let mut errors = Vec::new();
+        let explicit = apply_qualifier(&mut ty, field.qualifier.as_ref(), &mut errors);
+        if let Some(error) = errors.into_iter().next() {
+            return Err(in_field(error));
         }
 
-        if field.ty.array_specifier.is_some() {
-            return Err(crate::Error::ArraysUnsupported);
+        Ok(field.identifiers.0.iter().enumerate().map(move |(idx, ident)| {
+            let mut errors = Vec::new();
+            let sub = Self::extract_identifier(field, &ty, explicit, idx, ident, &mut errors);
+            match errors.into_iter().next() {
+                Some(error) => Err(error.in_declaration(DeclarationKind::Field, &sub.name)),
+                None => Ok(sub),
+            }
+        }))
+    }
+
+    /// Extracts the fields declared by one field specifier into `report`, along with every problem
+    fn report_fields(
+        field: &StructFieldSpecifier,
+        structs: &[AbstractStruct],
+        report: &mut FieldReport,
+    ) {
+        let first = &field.identifiers.0[0].ident.0;
+        let mut errors = Vec::new();
+
+        // A placeholder type still lets the arrays and qualifiers be checked
+        let mut ty = Self::resolve_type(field, structs).unwrap_or_else(|e| {
+            errors.push(e);
+            AbstractType::Float
+        });
+        let explicit = apply_qualifier(&mut ty, field.qualifier.as_ref(), &mut errors);
+        let shared_ok = errors.is_empty();
+        for error in errors {
+            report.push_error(first, error);
         }
 
-        let ty: AbstractType = field.ty.ty.clone().try_into()?;
+        for (idx, ident) in field.identifiers.0.iter().enumerate() {
+            let mut errors = Vec::new();
+            let sub = Self::extract_identifier(field, &ty, explicit, idx, ident, &mut errors);
+            if !errors.is_empty() {
+                for error in errors {
+                    report.push_error(&sub.name, error);
+                }
+            } else if shared_ok {
+                report.fields.push(sub);
+            }
+        }
+    }
 
-        Ok(field.identifiers.0.iter().map(move |ident| {
-            if ident.array_spec.is_some() {
-                return Err(crate::Error::ArraysUnsupported);
+    /// The type named by a field specifier, before any array dimensions or qualifiers
+    fn resolve_type(
+        field: &StructFieldSpecifier,
+        structs: &[AbstractStruct],
+    ) -> Result<AbstractType> {
+        match &field.ty.ty {
+            TypeSpecifierNonArray::TypeName(name) => structs
+                .iter()
+                .find(|s| s.name == name.0)
+                .map(|s| AbstractType::Struct(s.clone()))
+                .ok_or_else(|| crate::Error::UnknownStruct {
+                    name: name.0.clone(),
+                }),
+            TypeSpecifierNonArray::Struct(spec) => {
+                Ok(AbstractType::Struct(AbstractStruct::from_specifier(spec, structs)?))
             }
-            let name = ident.ident.0.clone();
-            Ok(Self { name, ty })
-        }))
+            ty => AbstractType::try_from(ty.clone()),
+        }
+    }
+
+    /// Extracts the field declared by one identifier of a field specifier, given its resolved type
+    /// Problems are added to `errors`, with the field wrapped in as many array dimensions as could be
+    fn extract_identifier(
+        field: &StructFieldSpecifier,
+        ty: &AbstractType,
+        explicit: ExplicitLayout,
+        idx: usize,
+        ident: &ArrayedIdentifier,
+        errors: &mut Vec<crate::Error>,
+    ) -> Self {
+        // Dimensions on the type (float[4] a) apply to every identifier,
+        // and dimensions on the identifier (float a[4]) are outermost
+        let mut ty = ty.clone();
+        if let Some(array) = &field.ty.array_specifier {
+            match array_type(ty.clone(), array, ident.array_spec.is_none()) {
+                Ok(array) => ty = array,
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Some(array) = &ident.array_spec {
+            match array_type(ty.clone(), array, true) {
+                Ok(array) => ty = array,
+                Err(e) => errors.push(e),
+            }
+        }
+        let name = ident.ident.0.clone();
+        if let Some(align) = explicit.align {
+            if let Err(e) = check_align(&name, align) {
+                errors.push(e);
+            }
+        }
+        // An offset only applies to the first identifier, the rest follow it as usual
+        let offset = if idx == 0 { explicit.offset } else { None };
+        Self {
+            name,
+            ty,
+            offset,
+            align: explicit.align,
+            row_major: explicit.row_major,
+        }
+    }
+}
+
+/// Wraps a type in each dimension of an array specifier, starting with the innermost (rightmost)
+/// Only the outermost dimension may be unsized, and only if `outermost` is set
+fn array_type(
+    mut ty: AbstractType,
+    array: &ArraySpecifier,
+    outermost: bool,
+) -> Result<AbstractType> {
+    let dimensions = &array.dimensions.0;
+    for (idx, dimension) in dimensions.iter().enumerate().rev() {
+        ty = match dimension {
+            ArraySpecifierDimension::ExplicitlySized(expr) => match constant_value(expr) {
+                Some(n) if n > 0 => AbstractType::Array(Box::new(ty), n as u64),
+                _ => return Err(crate::Error::ArraysUnsupported),
+            },
+            ArraySpecifierDimension::Unsized if outermost && idx == 0 => {
+                AbstractType::RuntimeArray(Box::new(ty))
+            }
+            ArraySpecifierDimension::Unsized => return Err(crate::Error::ArraysUnsupported),
+        };
+    }
+    Ok(ty)
+}
+
+/// Folds a constant integer expression, like the `(64 * 2)` a macro can leave in an array size
+/// Returns None for anything else, including overflow and division by zero
+fn constant_value(expr: &Expr) -> Option<i64> {
+    Some(match expr {
+        Expr::IntConst(n) => i64::from(*n),
+        Expr::UIntConst(n) => i64::from(*n),
+        Expr::Unary(UnaryOp::Add, e) => constant_value(e)?,
+        Expr::Unary(UnaryOp::Minus, e) => constant_value(e)?.checked_neg()?,
+        Expr::Unary(UnaryOp::Complement, e) => !constant_value(e)?,
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (constant_value(lhs)?, constant_value(rhs)?);
+            match op {
+                BinaryOp::Add => lhs.checked_add(rhs)?,
+                BinaryOp::Sub => lhs.checked_sub(rhs)?,
+                BinaryOp::Mult => lhs.checked_mul(rhs)?,
+                BinaryOp::Div => lhs.checked_div(rhs)?,
+                BinaryOp::Mod => lhs.checked_rem(rhs)?,
+                BinaryOp::LShift => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+                BinaryOp::RShift => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// The `offset`, `align` and majority layout qualifiers of a field
+#[derive(Copy, Clone, Default)]
+struct ExplicitLayout {
+    offset: Option<u64>,
+    align: Option<u64>,
+    row_major: Option<bool>,
+}
+
+/// Applies the layout qualifiers we understand (matrix majority) to a field's type,
+/// and returns any explicit offset and alignment
+/// Every qualifier we don't understand adds a problem to `errors`
+fn apply_qualifier(
+    ty: &mut AbstractType,
+    qualifier: Option<&TypeQualifier>,
+    errors: &mut Vec<crate::Error>,
+) -> ExplicitLayout {
+    let mut explicit = ExplicitLayout::default();
+    let specs = qualifier.map_or(&[][..], |q| &q.qualifiers.0);
+    for spec in specs {
+        let layout = match spec {
+            TypeQualifierSpec::Layout(layout) => layout,
+            _ => {
+                errors.push(crate::Error::QualifiersUnsupported);
+                continue;
+            }
+        };
+        for id in &layout.ids.0 {
+            let (ident, value) = match id {
+                LayoutQualifierSpec::Identifier(ident, value) => (ident.0.as_str(), value),
+                LayoutQualifierSpec::Shared => {
+                    errors.push(crate::Error::QualifiersUnsupported);
+                    continue;
+                }
+            };
+            let result = match (ident, value) {
+                ("row_major", None) => {
+                    ty.set_row_major(true);
+                    explicit.row_major = Some(true);
+                    Ok(())
+                }
+                ("column_major", None) => {
+                    ty.set_row_major(false);
+                    explicit.row_major = Some(false);
+                    Ok(())
+                }
+                ("offset", Some(value)) => literal_value(value).map(|v| explicit.offset = Some(v)),
+                ("align", Some(value)) => literal_value(value).map(|v| explicit.align = Some(v)),
+                _ => Err(crate::Error::QualifiersUnsupported),
+            };
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
     }
+    explicit
 }
 
-const FLOAT_ALIGN: u64 = 4;
-const FLOAT_SIZE: u64 = 4;
+/// The value of a layout qualifier like `offset = 16`, which we only support as a literal
+pub(crate) fn literal_value(expr: &Expr) -> Result<u64> {
+    match *expr {
+        Expr::IntConst(n) if n >= 0 => Ok(n as u64),
+        Expr::UIntConst(n) => Ok(n as u64),
+        _ => Err(crate::Error::QualifiersUnsupported),
+    }
+}
+
+/// An `align` qualifier has to be a power of two
+pub(crate) fn check_align(name: &str, align: u64) -> Result<()> {
+    if align.is_power_of_two() {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidAlign {
+            name: name.into(),
+            align,
+        })
+    }
+}
+
+/// Every 32 bit scalar we support (float, int, uint and bool) is 4 bytes wide in a buffer
+const SCALAR_SIZE: u64 = 4;
+const DOUBLE_SIZE: u64 = 8;
 
 impl AbstractType {
+    /// The vector type with `n` components of the given scalar type (or the scalar itself if `n` is 1)
+    pub fn vector(scalar: AbstractType, n: u64) -> AbstractType {
+        match (&scalar, n) {
+            (Self::Float, 1) => Self::Float,
+            (Self::Float, 2) => Self::Vec2,
+            (Self::Float, 3) => Self::Vec3,
+            (Self::Float, 4) => Self::Vec4,
+            (Self::Int, 1) => Self::Int,
+            (Self::Int, 2) => Self::IVec2,
+            (Self::Int, 3) => Self::IVec3,
+            (Self::Int, 4) => Self::IVec4,
+            (Self::UInt, 1) => Self::UInt,
+            (Self::UInt, 2) => Self::UVec2,
+            (Self::UInt, 3) => Self::UVec3,
+            (Self::UInt, 4) => Self::UVec4,
+            (Self::Bool, 1) => Self::Bool,
+            (Self::Bool, 2) => Self::BVec2,
+            (Self::Bool, 3) => Self::BVec3,
+            (Self::Bool, 4) => Self::BVec4,
+            (Self::Double, 1) => Self::Double,
+            (Self::Double, 2) => Self::DVec2,
+            (Self::Double, 3) => Self::DVec3,
+            (Self::Double, 4) => Self::DVec4,
+            _ => panic!("No vector type of {} {:?}s", n, scalar),
+        }
+    }
+
+    /// The scalar type making up each component of this type
+    ///
+    /// Panics for structs, which may be made up of several component types
+    pub fn component_type(&self) -> AbstractType {
+        match self {
+            Self::Float | Self::Vec2 | Self::Vec3 | Self::Vec4 => Self::Float,
+            Self::Int | Self::IVec2 | Self::IVec3 | Self::IVec4 => Self::Int,
+            Self::UInt | Self::UVec2 | Self::UVec3 | Self::UVec4 => Self::UInt,
+            Self::Bool | Self::BVec2 | Self::BVec3 | Self::BVec4 => Self::Bool,
+            Self::Double | Self::DVec2 | Self::DVec3 | Self::DVec4 => Self::Double,
+            Self::Matrix(m) if m.double => Self::Double,
+            Self::Matrix(_) => Self::Float,
+            Self::Array(elem, _) | Self::RuntimeArray(elem) => elem.component_type(),
+            Self::Struct(s) => panic!("Struct {} has no single component type", s.name),
+        }
+    }
+
+    /// Number of scalar components in this type
+    pub fn components(&self) -> u64 {
+        match self {
+            Self::Float | Self::Int | Self::UInt | Self::Bool | Self::Double => 1,
+            Self::Vec2 | Self::IVec2 | Self::UVec2 | Self::BVec2 | Self::DVec2 => 2,
+            Self::Vec3 | Self::IVec3 | Self::UVec3 | Self::BVec3 | Self::DVec3 => 3,
+            Self::Vec4 | Self::IVec4 | Self::UVec4 | Self::BVec4 | Self::DVec4 => 4,
+            Self::Matrix(m) => m.columns * m.rows,
+            Self::Array(elem, len) => elem.components() * len,
+            Self::RuntimeArray(_) => 0,
+            Self::Struct(s) => s.fields.iter().map(|f| f.ty.components()).sum(),
+        }
+    }
+
+    /// Size of a single scalar component of this type
+    pub fn component_size(&self) -> u64 {
+        match self.component_type() {
+            Self::Double => DOUBLE_SIZE,
+            _ => SCALAR_SIZE,
+        }
+    }
+
+    /// Sets the majority of matrix types (including arrays of them, and struct members without
+    /// their own majority qualifier); other types are unaffected, as in GLSL
+    pub fn set_row_major(&mut self, row_major: bool) {
+        match self {
+            Self::Matrix(m) => m.row_major = row_major,
+            Self::Array(elem, _) | Self::RuntimeArray(elem) => elem.set_row_major(row_major),
+            Self::Struct(s) => {
+                for field in s.fields.iter_mut().filter(|f| f.row_major.is_none()) {
+                    field.ty.set_row_major(row_major);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Whether this is a runtime-sized array, which contributes nothing to the fixed size of a block
+    pub fn is_runtime_array(&self) -> bool {
+        matches!(self, Self::RuntimeArray(_))
+    }
+
+    /// Alignment of the Rust type mirroring this one under `#[repr(C)]`
     pub fn align_c(&self) -> u64 {
-        todo!()
+        match self {
+            Self::Struct(s) => struct_align_c(&s.fields),
+            Self::Array(elem, _) | Self::RuntimeArray(elem) => elem.align_c(),
+            // Vectors and matrices become Rust arrays, which only need the alignment of a component
+            _ => self.component_size(),
+        }
     }
 
-    pub fn align_gl(&self) -> u64 {
+    /// Base alignment of this type under the given GLSL layout rule
+    pub fn align_gl(&self, rule: LayoutRule) -> u64 {
+        // Scalar block layout aligns everything to its component type
+        if rule == LayoutRule::Scalar {
+            return self.align_c();
+        }
+
         match self {
-            AbstractType::Float => FLOAT_ALIGN,
-            AbstractType::Vec2 => FLOAT_ALIGN * 2,
-            AbstractType::Vec3 => FLOAT_ALIGN * 4,
-            AbstractType::Vec4 => FLOAT_ALIGN * 4,
+            // Matrices are laid out like arrays of their column (or row) vectors
+            Self::Matrix(m) => m.vector().array_align_gl(rule),
+            Self::Array(elem, _) | Self::RuntimeArray(elem) => elem.array_align_gl(rule),
+            // Under std140 this is rounded up to the alignment of a vec4
+            Self::Struct(s) => struct_align_gl(&s.fields, rule),
+            // Scalars and vectors align identically under std140 and std430.
+            // Note this gives dvec3 and dvec4 a 32 byte alignment
+            _ => match self.components() {
+                3 => self.component_size() * 4,
+                n => self.component_size() * n,
+            },
         }
     }
 
+    /// Size of the tightly packed Rust mirror of this type
     pub fn size(&self) -> u64 {
         match self {
-            AbstractType::Float => FLOAT_SIZE,
-            AbstractType::Vec2 => FLOAT_SIZE * 2,
-            AbstractType::Vec3 => FLOAT_SIZE * 3,
-            AbstractType::Vec4 => FLOAT_SIZE * 4,
+            Self::Array(elem, len) => elem.size() * len,
+            Self::RuntimeArray(_) => 0,
+            Self::Struct(s) => layout_size(&naive_layout_c(&s.fields)),
+            _ => self.component_size() * self.components(),
+        }
+    }
+
+    /// Number of bytes this type occupies under the given GLSL layout rule
+    pub fn size_gl(&self, rule: LayoutRule) -> u64 {
+        match self {
+            // Includes the padding after each column, e.g. a std140 mat3 is 48 bytes
+            Self::Matrix(m) => m.vector_count() * m.vector().array_stride_gl(rule),
+            // Includes the padding after the last element, e.g. a std140 float[4] is 64 bytes
+            Self::Array(elem, len) => len * elem.array_stride_gl(rule),
+            Self::RuntimeArray(_) => 0,
+            // Includes the trailing padding up to the struct's alignment
+            Self::Struct(s) => layout_size(&naive_layout_glsl_only(&s.fields, rule)),
+            _ => self.size(),
+        }
+    }
+
+    /// Base alignment of an array with elements of this type
+    pub fn array_align_gl(&self, rule: LayoutRule) -> u64 {
+        match rule {
+            LayoutRule::Std140 => round_up(self.align_gl(rule), AbstractType::Vec4.align_gl(rule)),
+            LayoutRule::Std430 | LayoutRule::Scalar => self.align_gl(rule),
+        }
+    }
+
+    /// Distance in bytes between consecutive elements of an array of this type
+    pub fn array_stride_gl(&self, rule: LayoutRule) -> u64 {
+        round_up(self.size_gl(rule), self.array_align_gl(rule))
+    }
+
+    /// Name of the Rust type mirroring this one, padded out to match its size under the given rule
+    pub fn rust_type(&self, rule: LayoutRule) -> String {
+        let scalar = || match self.component_type() {
+            Self::Float => "f32",
+            Self::Int => "i32",
+            // GLSL bools are 32 bits wide in buffers, unlike Rust's single byte bool
+            Self::UInt | Self::Bool => "u32",
+            Self::Double => "f64",
+            _ => unreachable!("Component types are always scalars"),
+        };
+        match self {
+            // Refers to the Rust struct generated for it
+            Self::Struct(s) => s.name.clone(),
+            // Each column is widened to cover its padding, e.g. a std140 mat3 becomes [[f32; 4]; 3]
+            Self::Matrix(m) => format!(
+                "[{}; {}]",
+                m.vector().rust_type_padded(rule),
+                m.vector_count()
+            ),
+            Self::Array(elem, len) => format!("[{}; {}]", elem.rust_type_padded(rule), len),
+            // A trailing slice makes for a dynamically sized struct
+            Self::RuntimeArray(elem) => format!("[{}]", elem.rust_type_padded(rule)),
+            _ => match self.components() {
+                1 => scalar().to_string(),
+                n => format!("[{}; {}]", scalar(), n),
+            },
+        }
+    }
+
+    /// Name of the Rust type of an array element, padded out to the array stride
+    /// When the stride exceeds the element, it gets wrapped in a wider array covering its padding,
+    /// e.g. the elements of a std140 float[4] become [f32; 4]
+    fn rust_type_padded(&self, rule: LayoutRule) -> String {
+        let stride = self.array_stride_gl(rule);
+        if stride == self.size_gl(rule) {
+            return self.rust_type(rule);
         }
+        // Only scalars and vectors are ever smaller than their stride
+        let scalar = self.component_type().rust_type(rule);
+        format!("[{}; {}]", scalar, stride / self.component_size())
+    }
+}
+
+impl Matrix {
+    /// The vector type each column (or row, if row major) is stored as
+    pub fn vector(&self) -> AbstractType {
+        let scalar = if self.double {
+            AbstractType::Double
+        } else {
+            AbstractType::Float
+        };
+        let n = if self.row_major {
+            self.columns
+        } else {
+            self.rows
+        };
+        AbstractType::vector(scalar, n)
+    }
+
+    /// The number of column (or row, if row major) vectors stored
+    pub fn vector_count(&self) -> u64 {
+        if self.row_major {
+            self.rows
+        } else {
+            self.columns
+        }
+    }
+
+    fn new(columns: u64, rows: u64, double: bool) -> Self {
+        Self {
+            columns,
+            rows,
+            double,
+            row_major: false,
+        }
+    }
+}
+
+impl fmt::Display for AbstractType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Float => "float",
+            Self::Vec2 => "vec2",
+            Self::Vec3 => "vec3",
+            Self::Vec4 => "vec4",
+            Self::Int => "int",
+            Self::IVec2 => "ivec2",
+            Self::IVec3 => "ivec3",
+            Self::IVec4 => "ivec4",
+            Self::UInt => "uint",
+            Self::UVec2 => "uvec2",
+            Self::UVec3 => "uvec3",
+            Self::UVec4 => "uvec4",
+            Self::Bool => "bool",
+            Self::BVec2 => "bvec2",
+            Self::BVec3 => "bvec3",
+            Self::BVec4 => "bvec4",
+            Self::Double => "double",
+            Self::DVec2 => "dvec2",
+            Self::DVec3 => "dvec3",
+            Self::DVec4 => "dvec4",
+            Self::Struct(s) => s.name.as_str(),
+            Self::RuntimeArray(elem) => return write!(f, "{}[]", elem),
+            Self::Array(..) => {
+                // Dimensions are written outermost first, after the innermost element type
+                let mut dimensions = vec![];
+                let mut elem = self;
+                while let Self::Array(inner, len) = elem {
+                    dimensions.push(len);
+                    elem = inner;
+                }
+                write!(f, "{}", elem)?;
+                for len in dimensions {
+                    write!(f, "[{}]", len)?;
+                }
+                return Ok(());
+            }
+            Self::Matrix(m) => {
+                let prefix = if m.double { "d" } else { "" };
+                return if m.columns == m.rows {
+                    write!(f, "{}mat{}", prefix, m.columns)
+                } else {
+                    write!(f, "{}mat{}x{}", prefix, m.columns, m.rows)
+                };
+            }
+        };
+        f.write_str(name)
     }
 }
 
@@ -75,18 +782,99 @@ impl TryFrom<TypeSpecifierNonArray> for AbstractType {
             TypeSpecifierNonArray::Vec2 => Ok(Self::Vec2),
             TypeSpecifierNonArray::Vec3 => Ok(Self::Vec3),
             TypeSpecifierNonArray::Vec4 => Ok(Self::Vec4),
-            _ => Err(crate::Error::UnsupportedType { ty }),
+            TypeSpecifierNonArray::Int => Ok(Self::Int),
+            TypeSpecifierNonArray::IVec2 => Ok(Self::IVec2),
+            TypeSpecifierNonArray::IVec3 => Ok(Self::IVec3),
+            TypeSpecifierNonArray::IVec4 => Ok(Self::IVec4),
+            TypeSpecifierNonArray::UInt => Ok(Self::UInt),
+            TypeSpecifierNonArray::UVec2 => Ok(Self::UVec2),
+            TypeSpecifierNonArray::UVec3 => Ok(Self::UVec3),
+            TypeSpecifierNonArray::UVec4 => Ok(Self::UVec4),
+            TypeSpecifierNonArray::Bool => Ok(Self::Bool),
+            TypeSpecifierNonArray::BVec2 => Ok(Self::BVec2),
+            TypeSpecifierNonArray::BVec3 => Ok(Self::BVec3),
+            TypeSpecifierNonArray::BVec4 => Ok(Self::BVec4),
+            TypeSpecifierNonArray::Double => Ok(Self::Double),
+            TypeSpecifierNonArray::DVec2 => Ok(Self::DVec2),
+            TypeSpecifierNonArray::DVec3 => Ok(Self::DVec3),
+            TypeSpecifierNonArray::DVec4 => Ok(Self::DVec4),
+            TypeSpecifierNonArray::Mat2 => Ok(Self::Matrix(Matrix::new(2, 2, false))),
+            TypeSpecifierNonArray::Mat3 => Ok(Self::Matrix(Matrix::new(3, 3, false))),
+            TypeSpecifierNonArray::Mat4 => Ok(Self::Matrix(Matrix::new(4, 4, false))),
+            TypeSpecifierNonArray::Mat23 => Ok(Self::Matrix(Matrix::new(2, 3, false))),
+            TypeSpecifierNonArray::Mat24 => Ok(Self::Matrix(Matrix::new(2, 4, false))),
+            TypeSpecifierNonArray::Mat32 => Ok(Self::Matrix(Matrix::new(3, 2, false))),
+            TypeSpecifierNonArray::Mat34 => Ok(Self::Matrix(Matrix::new(3, 4, false))),
+            TypeSpecifierNonArray::Mat42 => Ok(Self::Matrix(Matrix::new(4, 2, false))),
+            TypeSpecifierNonArray::Mat43 => Ok(Self::Matrix(Matrix::new(4, 3, false))),
+            TypeSpecifierNonArray::DMat2 => Ok(Self::Matrix(Matrix::new(2, 2, true))),
+            TypeSpecifierNonArray::DMat3 => Ok(Self::Matrix(Matrix::new(3, 3, true))),
+            TypeSpecifierNonArray::DMat4 => Ok(Self::Matrix(Matrix::new(4, 4, true))),
+            TypeSpecifierNonArray::DMat23 => Ok(Self::Matrix(Matrix::new(2, 3, true))),
+            TypeSpecifierNonArray::DMat24 => Ok(Self::Matrix(Matrix::new(2, 4, true))),
+            TypeSpecifierNonArray::DMat32 => Ok(Self::Matrix(Matrix::new(3, 2, true))),
+            TypeSpecifierNonArray::DMat34 => Ok(Self::Matrix(Matrix::new(3, 4, true))),
+            TypeSpecifierNonArray::DMat42 => Ok(Self::Matrix(Matrix::new(4, 2, true))),
+            TypeSpecifierNonArray::DMat43 => Ok(Self::Matrix(Matrix::new(4, 3, true))),
+            _ => {
+                let mut name = String::new();
+                show_type_specifier_non_array(&mut name, &ty);
+                Err(crate::Error::UnsupportedType { ty: name })
+            }
         }
     }
 }
 
-impl Into<TypeSpecifierNonArray> for AbstractType {
-    fn into(self) -> TypeSpecifierNonArray {
-        match self {
-            Self::Float => TypeSpecifierNonArray::Float,
-            Self::Vec2 => TypeSpecifierNonArray::Vec2,
-            Self::Vec3 => TypeSpecifierNonArray::Vec3,
-            Self::Vec4 => TypeSpecifierNonArray::Vec4,
+impl From<AbstractType> for TypeSpecifierNonArray {
+    fn from(ty: AbstractType) -> Self {
+        match ty {
+            AbstractType::Float => TypeSpecifierNonArray::Float,
+            AbstractType::Vec2 => TypeSpecifierNonArray::Vec2,
+            AbstractType::Vec3 => TypeSpecifierNonArray::Vec3,
+            AbstractType::Vec4 => TypeSpecifierNonArray::Vec4,
+            AbstractType::Int => TypeSpecifierNonArray::Int,
+            AbstractType::IVec2 => TypeSpecifierNonArray::IVec2,
+            AbstractType::IVec3 => TypeSpecifierNonArray::IVec3,
+            AbstractType::IVec4 => TypeSpecifierNonArray::IVec4,
+            AbstractType::UInt => TypeSpecifierNonArray::UInt,
+            AbstractType::UVec2 => TypeSpecifierNonArray::UVec2,
+            AbstractType::UVec3 => TypeSpecifierNonArray::UVec3,
+            AbstractType::UVec4 => TypeSpecifierNonArray::UVec4,
+            AbstractType::Bool => TypeSpecifierNonArray::Bool,
+            AbstractType::BVec2 => TypeSpecifierNonArray::BVec2,
+            AbstractType::BVec3 => TypeSpecifierNonArray::BVec3,
+            AbstractType::BVec4 => TypeSpecifierNonArray::BVec4,
+            AbstractType::Double => TypeSpecifierNonArray::Double,
+            AbstractType::DVec2 => TypeSpecifierNonArray::DVec2,
+            AbstractType::DVec3 => TypeSpecifierNonArray::DVec3,
+            AbstractType::DVec4 => TypeSpecifierNonArray::DVec4,
+            AbstractType::Matrix(m) => match (m.double, m.columns, m.rows) {
+                (false, 2, 2) => TypeSpecifierNonArray::Mat2,
+                (false, 3, 3) => TypeSpecifierNonArray::Mat3,
+                (false, 4, 4) => TypeSpecifierNonArray::Mat4,
+                (false, 2, 3) => TypeSpecifierNonArray::Mat23,
+                (false, 2, 4) => TypeSpecifierNonArray::Mat24,
+                (false, 3, 2) => TypeSpecifierNonArray::Mat32,
+                (false, 3, 4) => TypeSpecifierNonArray::Mat34,
+                (false, 4, 2) => TypeSpecifierNonArray::Mat42,
+                (false, 4, 3) => TypeSpecifierNonArray::Mat43,
+                (true, 2, 2) => TypeSpecifierNonArray::DMat2,
+                (true, 3, 3) => TypeSpecifierNonArray::DMat3,
+                (true, 4, 4) => TypeSpecifierNonArray::DMat4,
+                (true, 2, 3) => TypeSpecifierNonArray::DMat23,
+                (true, 2, 4) => TypeSpecifierNonArray::DMat24,
+                (true, 3, 2) => TypeSpecifierNonArray::DMat32,
+                (true, 3, 4) => TypeSpecifierNonArray::DMat34,
+                (true, 4, 2) => TypeSpecifierNonArray::DMat42,
+                (true, 4, 3) => TypeSpecifierNonArray::DMat43,
+                _ => panic!("Invalid matrix dimensions {}x{}", m.columns, m.rows),
+            },
+            AbstractType::Array(..) | AbstractType::RuntimeArray(_) => {
+                panic!("Arrays are described by an ArraySpecifier, not a type")
+            }
+            AbstractType::Struct(s) => TypeSpecifierNonArray::TypeName(
+                TypeName::new(s.name.as_str()).expect("Non-ascii struct name"),
+            ),
         }
     }
 }
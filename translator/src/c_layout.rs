@@ -0,0 +1,84 @@
+use crate::abstract_data::*;
+use crate::glsl_layout::{compute_gap, ends_in_runtime_array, round_up, FieldGap, LayoutKind};
+use crate::type_map::TypeMap;
+
+/// Overall alignment of a `#[repr(C)]` struct holding these fields
+pub fn struct_align_c(fields: &[AbstractField]) -> u64 {
+    fields.iter().map(|f| f.ty.align_c()).max().unwrap_or(1)
+}
+
+/// Emulates rustc's `#[repr(C)]` layout algorithm (as on x86-64)
+/// Will produce a set of fields and gaps which match the Rust struct's layout, including trailing padding
+pub fn naive_layout_c(fields: &[AbstractField]) -> Vec<FieldGap> {
+    let mut output = Vec::new();
+    let mut offset = 0;
+    for field in fields {
+        let align = field.ty.align_c();
+        if let Some(gap) = FieldGap::alignment_gap(offset, field, align, LayoutKind::ReprC) {
+            offset += gap.size();
+            output.push(gap);
+        }
+        let size = field.ty.size();
+        output.push(FieldGap::Field(field.clone(), size));
+        offset += size;
+    }
+
+    // A trailing slice makes the struct dynamically sized, so there's no tail to pad
+    if ends_in_runtime_array(fields) {
+        return output;
+    }
+
+    if let Some(gap) = FieldGap::tail_gap(offset, struct_align_c(fields), LayoutKind::ReprC) {
+        output.push(gap);
+    }
+
+    output
+}
+
+/// Lays out fields and gaps as if every gap were declared as an explicit `[u8; N]` padding field of a `#[repr(C)]` struct
+/// Returns the offset of each entry, followed by the size of the whole struct
+pub fn explicit_offsets_c(fgs: &[FieldGap]) -> (Vec<u64>, u64) {
+    explicit_offsets_c_mapped(fgs, &TypeMap::default())
+}
+
+/// Like `explicit_offsets_c`, but with fields of mapped types aligned like the types they map onto
+pub fn explicit_offsets_c_mapped(fgs: &[FieldGap], map: &TypeMap) -> (Vec<u64>, u64) {
+    let mut offsets = Vec::with_capacity(fgs.len());
+    let mut offset = 0;
+    let mut struct_align = 1;
+    for fg in fgs {
+        let align = match fg {
+            FieldGap::Field(f, _) => map.align_c(&f.ty),
+            FieldGap::Gap(..) => 1,
+        };
+        struct_align = struct_align.max(align);
+        offset += compute_gap(offset, align).unwrap_or(0);
+        offsets.push(offset);
+        offset += fg.size();
+    }
+    (offsets, round_up(offset, struct_align))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glsl_layout::layout_size;
+
+    #[test]
+    fn test_vec3_packs_tightly() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("velocity", AbstractType::Vec3),
+        ];
+        let layout = naive_layout_c(&fields);
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout_size(&layout), 24);
+        assert_eq!(struct_align_c(&fields), 4);
+    }
+
+    #[test]
+    fn test_empty_struct() {
+        assert!(naive_layout_c(&[]).is_empty());
+        assert_eq!(struct_align_c(&[]), 1);
+    }
+}
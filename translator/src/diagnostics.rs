@@ -0,0 +1,414 @@
+use crate::preprocess::Preprocessed;
+use crate::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The kind of declaration an error happened in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Struct,
+    Block,
+    Field,
+}
+
+impl fmt::Display for DeclarationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Struct => "struct",
+            Self::Block => "block",
+            Self::Field => "field",
+        })
+    }
+}
+
+/// A range of characters on one line of a shader
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    /// The file the line is in, or None for source passed in directly
+    pub file: Option<PathBuf>,
+    /// Line number, counting from 1
+    pub line: usize,
+    /// Column the span starts at, counting from 1
+    pub column: usize,
+    pub len: usize,
+}
+
+/// An error along with the declaration it points at
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: Error,
+    pub span: Span,
+    /// The text of the line the span is on, as written before preprocessing
+    pub line_text: String,
+}
+
+impl fmt::Display for Diagnostic {
+    /// Renders the error with the offending declaration underlined, like rustc does
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = match &self.span.file {
+            Some(file) => file.display().to_string(),
+            None => "<source>".into(),
+        };
+        let gutter = " ".repeat(self.span.line.to_string().len());
+
+        writeln!(f, "error: {}", self.error.root())?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, file, self.span.line, self.span.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.span.line, self.line_text)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.span.column - 1),
+            "^".repeat(self.span.len.max(1))
+        )?;
+
+        let path = self.error.declaration_path();
+        if !path.is_empty() {
+            let path: Vec<String> = path
+                .iter()
+                .map(|(kind, name)| format!("{} {}", kind, name))
+                .collect();
+            write!(f, "\n{} = in {}", gutter, path.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Wraps an error with the declaration it happened in
+    pub(crate) fn in_declaration(self, kind: DeclarationKind, name: &str) -> Self {
+        Error::InDeclaration {
+            kind,
+            name: name.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error underneath any declarations and locations attached to it
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::InDeclaration { source, .. } => source.root(),
+            Error::Located(diagnostic) => diagnostic.error.root(),
+            error => error,
+        }
+    }
+
+    /// The declarations the error happened in, outermost first
+    pub fn declaration_path(&self) -> Vec<(DeclarationKind, &str)> {
+        let mut path = Vec::new();
+        let mut error = self;
+        loop {
+            match error {
+                Error::InDeclaration { kind, name, source } => {
+                    path.push((*kind, name.as_str()));
+                    error = source;
+                }
+                Error::Located(diagnostic) => error = &diagnostic.error,
+                _ => break,
+            }
+        }
+
+        // Some errors name the field they're about, without it being wrapped around them
+        let field = match error {
+            Error::MisplacedRuntimeArray { name }
+            | Error::InvalidAlign { name, .. }
+            | Error::MisalignedOffset { name, .. }
+            | Error::OverlappingOffset { name, .. }
+            | Error::ExplicitLayoutOutsideBlock { name } => Some(name.as_str()),
+            _ => None,
+        };
+        if let Some(field) = field {
+            if path.last() != Some(&(DeclarationKind::Field, field)) {
+                path.push((DeclarationKind::Field, field));
+            }
+        }
+        path
+    }
+}
+
+/// Attaches the location of the declaration an error happened in, if it can be found in the source
+pub fn diagnose(error: Error, source: &Preprocessed) -> Error {
+    // Names and braces inside comments mustn't be mistaken for declarations
+    let code = mask_comments(&source.source);
+    let span = match locate(&code, &error.declaration_path()) {
+        Some(span) => span,
+        None => return error,
+    };
+
+    // Only the first line of a declaration spanning several gets underlined
+    let line_start = code[..span.0].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = code[line_start..]
+        .find('\n')
+        .map_or(code.len(), |idx| line_start + idx);
+    let line_index = code[..line_start].matches('\n').count();
+    let origin = match source.lines.get(line_index) {
+        Some(origin) => origin,
+        None => return error,
+    };
+
+    // The line gets shown as written, so the span has to be moved back over any macro expansions
+    let expanded = &source.source[line_start..line_end];
+    let (start, end) = original_range(
+        expanded,
+        &origin.text,
+        span.0 - line_start,
+        span.1.min(line_end) - line_start,
+    );
+    Error::Located(Box::new(Diagnostic {
+        error,
+        span: Span {
+            file: origin.file.clone(),
+            line: origin.line,
+            column: origin.text[..start].chars().count() + 1,
+            len: origin.text[start..end].chars().count(),
+        },
+        line_text: origin.text.clone(),
+    }))
+}
+
+/// Replaces every comment with spaces, keeping the byte offset of everything else (and line breaks)
+fn mask_comments(source: &str) -> String {
+    let mut masked = String::with_capacity(source.len());
+    let mut rest = source;
+    let mut in_comment = false;
+    while let Some(c) = rest.chars().next() {
+        let len = if in_comment && rest.starts_with("*/") {
+            in_comment = false;
+            2
+        } else if !in_comment && rest.starts_with("/*") {
+            in_comment = true;
+            2
+        } else if !in_comment && rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if in_comment && c != '\n' {
+            c.len_utf8()
+        } else {
+            masked.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        masked.push_str(&" ".repeat(len));
+        rest = &rest[len..];
+    }
+    masked
+}
+
+/// Maps a byte range of a line after macro expansion onto the line as written
+/// The two are assumed to differ in one stretch, where the expansion happened, and a range
+/// reaching into that stretch covers the whole of it
+fn original_range(expanded: &str, original: &str, start: usize, end: usize) -> (usize, usize) {
+    let prefix: usize = expanded
+        .chars()
+        .zip(original.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let suffix: usize = expanded[prefix..]
+        .chars()
+        .rev()
+        .zip(original[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    let changed_end = expanded.len() - suffix;
+    let map = |offset: usize, inside: usize| {
+        if offset <= prefix {
+            offset
+        } else if offset >= changed_end {
+            offset - changed_end + original.len() - suffix
+        } else {
+            inside
+        }
+    };
+    (map(start, prefix), map(end, original.len() - suffix))
+}
+
+/// Finds the byte range of the innermost declaration along a path, by searching for each name in turn
+/// within the declaration before it
+fn locate(source: &str, path: &[(DeclarationKind, &str)]) -> Option<(usize, usize)> {
+    let mut range = (0, source.len());
+    let mut found = None;
+    for &(kind, name) in path {
+        let (start, end) = range;
+        let mut search = start;
+        let position = loop {
+            let position = search + find_word(&source[search..end], name)?;
+            let after = source[position + name.len()..end].trim_start();
+            // Struct and block names are followed by their body, which skips over uses as a type
+            if kind == DeclarationKind::Field || after.starts_with('{') {
+                break position;
+            }
+            search = position + name.len();
+        };
+
+        range = match kind {
+            DeclarationKind::Field => declaration_bounds(source, position, start, end),
+            _ => {
+                let open = position + source[position..end].find('{')?;
+                (position, matching_brace(source, open, end))
+            }
+        };
+        found = Some(range);
+    }
+    found
+}
+
+/// Byte offset of the first occurrence of `word` in `text` which isn't part of a longer identifier
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut offset = 0;
+    while let Some(idx) = text[offset..].find(word) {
+        let start = offset + idx;
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(is_ident) && !after.is_some_and(is_ident) {
+            return Some(start);
+        }
+        offset = end;
+    }
+    None
+}
+
+/// The byte range of the field declaration around `position`, from after the previous `;`, `{` or `}`
+/// up to and including the next `;`, skipping over any struct bodies along the way
+fn declaration_bounds(source: &str, position: usize, start: usize, end: usize) -> (usize, usize) {
+    let bytes = source.as_bytes();
+
+    let mut depth = 0;
+    let mut from = start;
+    for idx in (start..position).rev() {
+        match bytes[idx] {
+            b'}' => depth += 1,
+            b'{' if depth == 0 => {
+                from = idx + 1;
+                break;
+            }
+            b'{' => depth -= 1,
+            b';' if depth == 0 => {
+                from = idx + 1;
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let mut to = end;
+    let mut depth = 0;
+    for (idx, &byte) in bytes.iter().enumerate().take(end).skip(position) {
+        match byte {
+            b'{' => depth += 1,
+            b'}' if depth == 0 => {
+                to = idx;
+                break;
+            }
+            b'}' => depth -= 1,
+            b';' if depth == 0 => {
+                to = idx + 1;
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    // Leading whitespace (including the newline after the previous declaration) isn't part of it
+    let leading = source[from..to].len() - source[from..to].trim_start().len();
+    (from + leading, to)
+}
+
+/// The byte offset just past the brace closing the one at `open`
+fn matching_brace(source: &str, open: usize, end: usize) -> usize {
+    let mut depth = 0;
+    for (idx, byte) in source.bytes().enumerate().take(end).skip(open) {
+        match byte {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return idx + 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extraction::parse_shader;
+
+    #[test]
+    fn test_caret_snippet() {
+        let source = "struct Light {
+    vec3 color;
+    float spots[2], flux[count];
+};";
+        let error = parse_shader(source).unwrap_err();
+        assert!(matches!(error.root(), crate::Error::ArraysUnsupported));
+        let expected = "error: Currently, we only support arrays with a constant integer size
+ --> <source>:3:5
+  |
+3 |     float spots[2], flux[count];
+  |     ^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+  = in struct Light, field flux";
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn test_skips_comments() {
+        let source = "struct Light { // tex is bound elsewhere
+    vec3 color;
+    sampler2D tex;
+};";
+        let error = parse_shader(source).unwrap_err();
+        match &error {
+            crate::Error::Located(diagnostic) => {
+                assert_eq!(diagnostic.span.line, 3);
+                assert_eq!(diagnostic.line_text.trim(), "sampler2D tex;");
+            }
+            _ => panic!("Expected a located error, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_shows_line_before_expansion() {
+        let source = "#define N 0
+#define WEIGHT_TYPE float
+struct Weights {
+    uint count; WEIGHT_TYPE w[N];
+};";
+        let error = parse_shader(source).unwrap_err();
+        let expected = "error: Currently, we only support arrays with a constant integer size
+ --> <source>:4:17
+  |
+4 |     uint count; WEIGHT_TYPE w[N];
+  |                 ^^^^^^^^^^^^^^^^^
+  = in struct Weights, field w";
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn test_nested_declaration() {
+        let source = "
+struct Light { vec3 color; };
+layout(std140) uniform Lights {
+    Light lights[4];
+    layout(offset = 4) vec4 ambient;
+};";
+        let error = parse_shader(source).unwrap_err();
+        match &error {
+            crate::Error::Located(diagnostic) => {
+                assert_eq!(diagnostic.span.line, 5);
+                assert_eq!(diagnostic.line_text.trim(), "layout(offset = 4) vec4 ambient;");
+            }
+            _ => panic!("Expected a located error, got {:?}", error),
+        }
+    }
+}
@@ -1,39 +1,700 @@
-use glsl::syntax::StructFieldSpecifier;
+use glsl::parser::Parse;
+use glsl::syntax::{
+    Block, LayoutQualifierSpec, ShaderStage, StorageQualifier, StructSpecifier,
+    TypeQualifierSpec,
+};
 use glsl::visitor::{Host, Visit, Visitor};
-use crate::abstract_data::AbstractField;
+use crate::abstract_data::{
+    check_align, literal_value, AbstractField, AbstractStruct, FieldReport,
+};
+use crate::diagnostics::{diagnose, DeclarationKind};
+use crate::glsl_layout::{validate_explicit_layout, LayoutRule};
+use crate::preprocess::{preprocess, preprocess_file, PreprocessOptions, Preprocessed};
 use crate::Result;
+use std::path::Path;
 
+/// Whether a definition is a plain struct or an interface block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Struct,
+    Block,
+}
+
+/// A struct or interface block found in a shader, by the name it was declared with
+#[derive(Clone, Debug)]
+pub struct ShaderDefinition {
+    pub name: String,
+    /// Name of the block's instance, if it has one
+    pub instance: Option<String>,
+    pub kind: DefinitionKind,
+    pub fields: Vec<AbstractField>,
+    /// Layout qualifiers, for interface blocks only
+    pub layout: Option<BlockLayout>,
+}
+
+impl ShaderDefinition {
+    /// The layout rule the definition's fields follow
+    /// Plain structs take on the rule of whichever block holds them, so they get the default
+    pub fn rule(&self) -> LayoutRule {
+        self.layout.map(|l| l.rule).unwrap_or_default()
+    }
+}
+
+/// What kind of memory an interface block is backed by
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockStorage {
+    Uniform,
+    Buffer,
+    PushConstant,
+    /// Shader inputs and outputs, which have no layout in memory
+    /// These are left out when listing a shader's definitions
+    Other,
+}
+
+impl BlockStorage {
+    /// Reads the storage qualifiers of a block
+    pub fn of(block: &Block) -> Self {
+        let mut storage = BlockStorage::Other;
+        for spec in &block.qualifier.qualifiers.0 {
+            match spec {
+                TypeQualifierSpec::Storage(StorageQualifier::Uniform) => {
+                    storage = BlockStorage::Uniform
+                }
+                TypeQualifierSpec::Storage(StorageQualifier::Buffer) => {
+                    storage = BlockStorage::Buffer
+                }
+                TypeQualifierSpec::Layout(layout) => {
+                    let push_constant = layout.ids.0.iter().any(|id| match id {
+                        LayoutQualifierSpec::Identifier(ident, None) => ident.0 == "push_constant",
+                        _ => false,
+                    });
+                    if push_constant {
+                        return BlockStorage::PushConstant;
+                    }
+                }
+                _ => (),
+            }
+        }
+        storage
+    }
+}
+
+/// The layout qualifiers of an interface block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockLayout {
+    pub storage: BlockStorage,
+    /// Given explicitly, or else std140 for uniform blocks and std430 for buffer blocks and push constants
+    pub rule: LayoutRule,
+    pub set: Option<u64>,
+    pub binding: Option<u64>,
+    /// Minimum alignment of every member without an `align` qualifier of its own
+    pub align: Option<u64>,
+    /// Majority of the matrices in every member without a `row_major` or `column_major` of its own
+    pub row_major: Option<bool>,
+}
+
+impl BlockLayout {
+    /// Reads the storage and layout qualifiers of a block
+    /// Qualifiers which don't affect the layout (like `location` or `readonly`) are ignored
+    pub fn from_block(block: &Block) -> Result<Self> {
+        let storage = BlockStorage::of(block);
+        let mut rule = None;
+        let mut set = None;
+        let mut binding = None;
+        let mut align = None;
+        let mut row_major = None;
+
+        let unsupported = |layout: &str| {
+            Err(crate::Error::UnsupportedBlockLayout {
+                name: block.name.0.clone(),
+                layout: layout.into(),
+            })
+        };
+
+        for spec in &block.qualifier.qualifiers.0 {
+            if let TypeQualifierSpec::Layout(layout) = spec {
+                for id in &layout.ids.0 {
+                    let (ident, value) = match id {
+                        LayoutQualifierSpec::Shared => return unsupported("shared"),
+                        LayoutQualifierSpec::Identifier(ident, value) => (ident, value),
+                    };
+                    match (ident.0.as_str(), value) {
+                        ("std140", None) => rule = Some(LayoutRule::Std140),
+                        ("std430", None) => rule = Some(LayoutRule::Std430),
+                        ("scalar", None) => rule = Some(LayoutRule::Scalar),
+                        ("packed", None) => return unsupported("packed"),
+                        ("row_major", None) => row_major = Some(true),
+                        ("column_major", None) => row_major = Some(false),
+                        ("set", Some(value)) => set = Some(literal_value(value)?),
+                        ("binding", Some(value)) => binding = Some(literal_value(value)?),
+                        ("align", Some(value)) => {
+                            let value = literal_value(value)?;
+                            check_align(&block.name.0, value)?;
+                            align = Some(value);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        let rule = rule.unwrap_or(match storage {
+            BlockStorage::Buffer | BlockStorage::PushConstant => LayoutRule::Std430,
+            BlockStorage::Uniform | BlockStorage::Other => LayoutRule::Std140,
+        });
+
+        Ok(Self {
+            storage,
+            rule,
+            set,
+            binding,
+            align,
+            row_major,
+        })
+    }
+}
+
+
+/// Parses a shader, listing every struct and interface block it defines in order
+pub fn parse_shader(source: &str) -> Result<Vec<ShaderDefinition>> {
+    parse_shader_with(source, &PreprocessOptions::default())
+}
+
+/// Like `parse_shader`, preprocessing with the given include paths and defines
+pub fn parse_shader_with(
+    source: &str,
+    options: &PreprocessOptions,
+) -> Result<Vec<ShaderDefinition>> {
+    parse_preprocessed(&preprocess(source, options)?)
+}
+
+/// Reads and parses a shader file, listing every struct and interface block it defines in order
+pub fn parse_shader_file<P: AsRef<Path>>(path: P) -> Result<Vec<ShaderDefinition>> {
+    parse_shader_file_with(path, &PreprocessOptions::default())
+}
+
+/// Like `parse_shader_file`, preprocessing with the given include paths and defines
+pub fn parse_shader_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &PreprocessOptions,
+) -> Result<Vec<ShaderDefinition>> {
+    parse_preprocessed(&preprocess_file(path, options)?)
+}
+
+/// Errors in a declaration get pointed at it in the original source
+fn parse_preprocessed(source: &Preprocessed) -> Result<Vec<ShaderDefinition>> {
+    let mut stage = ShaderStage::parse(&source.source).map_err(|e| crate::Error::Parse {
+        message: e.info,
+    })?;
+    get_definitions(&mut stage).map_err(|e| diagnose(e, source))
+}
+
+/// Parses a shader and extracts only the struct or interface block called `name`,
+/// so problems elsewhere in the shader don't get in the way
+pub fn parse_shader_definition(source: &str, name: &str) -> Result<ShaderDefinition> {
+    let source = preprocess(source, &PreprocessOptions::default())?;
+    parse_preprocessed_definition(&source, name)
+}
+
+/// Like `parse_shader_definition`, reading the shader from a file and preprocessing it with the
+/// given include paths and defines
+pub fn parse_shader_file_definition_with<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    options: &PreprocessOptions,
+) -> Result<ShaderDefinition> {
+    parse_preprocessed_definition(&preprocess_file(path, options)?, name)
+}
+
+fn parse_preprocessed_definition(source: &Preprocessed, name: &str) -> Result<ShaderDefinition> {
+    let mut stage = ShaderStage::parse(&source.source).map_err(|e| crate::Error::Parse {
+        message: e.info,
+    })?;
+    get_definition(&mut stage, name).map_err(|e| diagnose(e, source))
+}
+
+/// Finds a struct or interface block by its name, or the name of a block's instance
+pub fn find_definition<'a>(
+    definitions: &'a [ShaderDefinition],
+    name: &str,
+) -> Result<&'a ShaderDefinition> {
+    definitions
+        .iter()
+        .find(|d| d.name == name || d.instance.as_deref() == Some(name))
+        .ok_or_else(|| crate::Error::DefinitionNotFound { name: name.into() })
+}
+
+/// Parses a shader and extracts the fields of the struct or interface block called `name`
+pub fn get_fields_by_name(source: &str, name: &str) -> Result<Vec<AbstractField>> {
+    Ok(parse_shader_definition(source, name)?.fields)
+}
+
+/// Lists every struct and interface block found in `structure`, in order
+/// Fields with a struct type are resolved against the structs defined before them
+/// Blocks of shader inputs and outputs have no layout in memory, and anonymous structs can't be
+/// referred to by name, so both are left out
+pub fn get_definitions<H: Host>(structure: &mut H) -> Result<Vec<ShaderDefinition>> {
+    let mut definitions = Vec::new();
+    let mut structs = Vec::new();
+    let mut extractor = FieldExtractor::new();
+
+    structure.visit(&mut extractor);
+
+    for definition in extractor.finish().into_iter().filter(Definition::is_listed) {
+        let definition = extract_definition(&definition, &structs)?;
+        if definition.kind == DefinitionKind::Struct {
+            structs.push(AbstractStruct {
+                name: definition.name.clone(),
+                fields: definition.fields.clone(),
+            });
+        }
+        definitions.push(definition);
+    }
+
+    Ok(definitions)
+}
+
+/// Extracts only the struct or interface block called `name` (or the block whose instance is)
+/// Other definitions may fail to extract, as long as the selected one doesn't refer to them
+pub fn get_definition<H: Host>(structure: &mut H, name: &str) -> Result<ShaderDefinition> {
+    let mut structs = Vec::new();
+    // Structs which failed to extract, along with why
+    let mut failed = Vec::new();
+    let mut extractor = FieldExtractor::new();
+
+    structure.visit(&mut extractor);
+
+    for definition in extractor.finish().into_iter().filter(Definition::is_listed) {
+        if definition.is_named(name) {
+            return extract_definition(&definition, &structs).map_err(|e| {
+                // Using a broken struct reports the struct's own error, not an unknown name
+                let broken = match e.root() {
+                    crate::Error::UnknownStruct { name } => {
+                        failed.iter().position(|(failed, _)| failed == name)
+                    }
+                    _ => None,
+                };
+                match broken {
+                    Some(idx) => failed.swap_remove(idx).1,
+                    None => e,
+                }
+            });
+        }
+        if let Definition::Struct(spec) = &definition {
+            match AbstractStruct::from_specifier(spec, &structs) {
+                Ok(abstract_struct) => structs.push(abstract_struct),
+                Err(e) => failed.extend(spec.name.as_ref().map(|n| (n.0.clone(), e))),
+            }
+        }
+    }
+
+    Err(crate::Error::DefinitionNotFound { name: name.into() })
+}
+
+/// Extracts a struct or interface block, given the structs defined before it
+fn extract_definition(
+    definition: &Definition,
+    structs: &[AbstractStruct],
+) -> Result<ShaderDefinition> {
+    match definition {
+        Definition::Struct(spec) => {
+            let abstract_struct = AbstractStruct::from_specifier(spec, structs)?;
+            Ok(ShaderDefinition {
+                name: abstract_struct.name,
+                instance: None,
+                kind: DefinitionKind::Struct,
+                fields: abstract_struct.fields,
+                layout: None,
+            })
+        }
+        Definition::Block(block) => block_definition(block, structs)
+            .map_err(|e| e.in_declaration(DeclarationKind::Block, &block.name.0)),
+    }
+}
+
+fn block_definition(block: &Block, structs: &[AbstractStruct]) -> Result<ShaderDefinition> {
+    let (layout, report) = report_block(block, structs)?;
+    Ok(ShaderDefinition {
+        name: block.name.0.clone(),
+        instance: block.identifier.as_ref().map(|i| i.ident.0.clone()),
+        kind: DefinitionKind::Block,
+        fields: report.into_result()?,
+        layout: Some(layout),
+    })
+}
+
+/// Extracts a block's layout, and its fields along with every problem with them
+/// Only problems with the block's own qualifiers stop extraction
+fn report_block(block: &Block, structs: &[AbstractStruct]) -> Result<(BlockLayout, FieldReport)> {
+    let layout = BlockLayout::from_block(block)?;
+    let mut report = AbstractField::report_all(&block.fields, structs);
+    for field in &mut report.fields {
+        field.align = field.align.or(layout.align);
+    }
+    if let Some(row_major) = layout.row_major {
+        for field in report.fields.iter_mut().filter(|f| f.row_major.is_none()) {
+            field.ty.set_row_major(row_major);
+        }
+    }
+    // Explicit offsets can only be checked against each other once every field is known
+    if report.is_ok() {
+        if let Err(e) = validate_explicit_layout(&report.fields, layout.rule) {
+            let field = match e.declaration_path().last() {
+                Some((_, name)) => name.to_string(),
+                None => block.name.0.clone(),
+            };
+            report.push_error(&field, e);
+        }
+    }
+    Ok((layout, report))
+}
+
+/// Extracts every field of every struct and interface block found in `structure`, in order
+/// Fields with a struct type are resolved against the structs defined before them
+/// Use `get_definitions` or `get_fields_by_name` to keep each definition's fields apart
 pub fn get_abstract_fields<H: Host>(structure: &mut H) -> Result<Vec<AbstractField>> {
-    let mut abstract_fields = Vec::new();
+    let mut fields = Vec::new();
+    let mut structs = Vec::new();
     let mut extractor = FieldExtractor::new();
 
     structure.visit(&mut extractor);
-    let glsl_fields = extractor.finish();
 
-    for field in &glsl_fields {
-        for sub in AbstractField::extract_fields(field)? {
-            abstract_fields.push(sub?);
+    for definition in extractor.finish() {
+        match &definition {
+            // Anonymous structs can't be referred to, but their fields still count
+            Definition::Struct(spec) if spec.name.is_none() => {
+                fields.extend(AbstractStruct::report_specifier(spec, &structs).into_result()?);
+            }
+            _ => {
+                let definition = extract_definition(&definition, &structs)?;
+                if definition.kind == DefinitionKind::Struct {
+                    structs.push(AbstractStruct {
+                        name: definition.name.clone(),
+                        fields: definition.fields.clone(),
+                    });
+                }
+                fields.extend(definition.fields);
+            }
         }
     }
 
-    Ok(abstract_fields)
+    Ok(fields)
 }
 
-struct FieldExtractor(Vec<StructFieldSpecifier>);
+/// Like `get_abstract_fields`, but finds every problem with the fields at once,
+/// keeping the fields that did extract
+/// Only structs which extract cleanly can be referred to by later definitions
+pub fn get_abstract_fields_report<H: Host>(structure: &mut H) -> Result<FieldReport> {
+    let mut report = FieldReport::default();
+    let mut structs = Vec::new();
+    let mut extractor = FieldExtractor::new();
+
+    structure.visit(&mut extractor);
+
+    for definition in extractor.finish() {
+        let part = match &definition {
+            Definition::Struct(spec) => {
+                let part = AbstractStruct::report_specifier(spec, &structs);
+                if let (Some(name), true) = (&spec.name, part.is_ok()) {
+                    structs.push(AbstractStruct {
+                        name: name.0.clone(),
+                        fields: part.fields.clone(),
+                    });
+                }
+                part
+            }
+            Definition::Block(block) => report_block(block, &structs)?.1,
+        };
+        report.fields.extend(part.fields);
+        report.errors.extend(part.errors);
+    }
+
+    Ok(report)
+}
+
+/// A struct or interface block definition, in the order it was found
+enum Definition {
+    Struct(StructSpecifier),
+    Block(Block),
+}
+
+impl Definition {
+    /// Whether the definition is listed on its own: named structs, and blocks laid out in memory
+    fn is_listed(&self) -> bool {
+        match self {
+            Definition::Struct(spec) => spec.name.is_some(),
+            Definition::Block(block) => BlockStorage::of(block) != BlockStorage::Other,
+        }
+    }
+
+    /// Whether the definition was declared as `name`, or is a block whose instance is
+    fn is_named(&self, name: &str) -> bool {
+        match self {
+            Definition::Struct(spec) => spec.name.as_ref().is_some_and(|n| n.0 == name),
+            Definition::Block(block) => {
+                block.name.0 == name
+                    || block.identifier.as_ref().is_some_and(|i| i.ident.0 == name)
+            }
+        }
+    }
+}
+
+struct FieldExtractor(Vec<Definition>);
 
 impl FieldExtractor {
     pub fn new() -> Self {
         Self(vec![])
     }
 
-    pub fn finish(self) -> Vec<StructFieldSpecifier> {
+    pub fn finish(self) -> Vec<Definition> {
         self.0
     }
 }
 
 impl Visitor for FieldExtractor {
-    fn visit_struct_field_specifier(&mut self, field: &mut StructFieldSpecifier) -> Visit {
-        self.0.push(field.clone());
+    // Nested struct definitions are converted along with the struct containing them
+    fn visit_struct_specifier(&mut self, spec: &mut StructSpecifier) -> Visit {
+        self.0.push(Definition::Struct(spec.clone()));
+        Visit::Parent
+    }
+
+    fn visit_block(&mut self, block: &mut Block) -> Visit {
+        self.0.push(Definition::Block(block.clone()));
         Visit::Parent
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abstract_data::AbstractType;
+
+    const PARTICLE_BASE: &str = include_str!("../../shader_examples/particle_base.comp");
+
+    #[test]
+    fn test_list_definitions() {
+        let definitions = parse_shader(PARTICLE_BASE).unwrap();
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Particle", "Vertex", "VertexBuffer", "ParticleBuffer"]);
+        assert_eq!(definitions[2].kind, DefinitionKind::Block);
+
+        let layout = definitions[3].layout.unwrap();
+        assert_eq!(layout.storage, BlockStorage::Buffer);
+        assert_eq!(layout.rule, LayoutRule::Std430);
+        assert_eq!(layout.binding, Some(1));
+        assert_eq!(definitions[0].layout, None);
+    }
+
+    #[test]
+    fn test_block_layout() {
+        let source = "
+            layout(std430, set = 1, binding = 2) uniform Globals {
+                vec3 sun;
+                float time;
+            };
+            layout(binding = 0) uniform Camera {
+                mat4 view;
+            } camera;
+        ";
+        let definitions = parse_shader(source).unwrap();
+        let globals = find_definition(&definitions, "Globals").unwrap().layout.unwrap();
+        assert_eq!(globals.storage, BlockStorage::Uniform);
+        assert_eq!(globals.rule, LayoutRule::Std430);
+        assert_eq!((globals.set, globals.binding), (Some(1), Some(2)));
+
+        let camera = find_definition(&definitions, "camera").unwrap();
+        assert_eq!(camera.name, "Camera");
+        assert_eq!(camera.rule(), LayoutRule::Std140);
+
+        let source = "
+            layout(std430, align = 16) buffer Locked {
+                float time;
+                layout(offset = 32) vec3 sun;
+            };
+        ";
+        let locked = &parse_shader(source).unwrap()[0];
+        assert_eq!(locked.fields[0].align, Some(16));
+        assert_eq!(locked.fields[1].offset, Some(32));
+
+        let overlapping = "uniform Bad { vec4 a; layout(offset = 8) float b; };";
+        let overlapping = parse_shader(overlapping).unwrap_err();
+        assert!(matches!(overlapping.root(), crate::Error::OverlappingOffset { .. }));
+
+        let source = "#define LIGHTS 4\nuniform Lights { vec4 colors[LIGHTS]; };";
+        let lights = &parse_shader(source).unwrap()[0];
+        let vec4s = AbstractType::Array(Box::new(AbstractType::Vec4), 4);
+        assert_eq!(lights.fields[0].ty, vec4s);
+
+        // Macros often expand to constant expressions rather than literals
+        let source = "#define N 64\n#define COUNT (N * 2)\nuniform Values { float values[COUNT]; };";
+        let values = &parse_shader(source).unwrap()[0];
+        let floats = AbstractType::Array(Box::new(AbstractType::Float), 128);
+        assert_eq!(values.fields[0].ty, floats);
+
+        // Members take on the block's matrix majority unless they give their own
+        let source = "
+            layout(std140, row_major) uniform Rm {
+                mat2x3 m;
+                layout(column_major) mat2x3 c;
+            };
+        ";
+        let rm = &parse_shader(source).unwrap()[0];
+        assert_eq!(rm.layout.unwrap().row_major, Some(true));
+        let sizes: Vec<u64> = rm.fields.iter().map(|f| f.ty.size_gl(LayoutRule::Std140)).collect();
+        assert_eq!(sizes, [48, 32]);
+
+        // The block's majority reaches into structs, except members with a majority of their own
+        let source = "
+            struct Frame { mat3 rotation; layout(column_major) mat3 fixed; };
+            layout(std140, row_major) uniform Frames { Frame frame; };
+        ";
+        let definitions = parse_shader(source).unwrap();
+        let frames = find_definition(&definitions, "Frames").unwrap();
+        let frame = match &frames.fields[0].ty {
+            AbstractType::Struct(frame) => frame,
+            other => panic!("Expected a struct, got {}", other),
+        };
+        let majority: Vec<bool> = frame
+            .fields
+            .iter()
+            .map(|f| matches!(&f.ty, AbstractType::Matrix(m) if m.row_major))
+            .collect();
+        assert_eq!(majority, [true, false]);
+
+        let packed = parse_shader("layout(packed) uniform Packed { float x; };").unwrap_err();
+        assert!(matches!(packed.root(), crate::Error::UnsupportedBlockLayout { .. }));
+    }
+
+    #[test]
+    fn test_integer_and_bool_fields() {
+        let source = "
+            layout(std430) buffer Flags {
+                int i; uint u; bool b;
+                ivec3 iv; uvec2 uv; bvec4 bv;
+            };
+        ";
+        let flags = &parse_shader(source).unwrap()[0];
+        let types: Vec<&AbstractType> = flags.fields.iter().map(|f| &f.ty).collect();
+        let expected = [
+            AbstractType::Int,
+            AbstractType::UInt,
+            AbstractType::Bool,
+            AbstractType::IVec3,
+            AbstractType::UVec2,
+            AbstractType::BVec4,
+        ];
+        assert_eq!(types, expected.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_select_by_name() {
+        let fields = get_fields_by_name(PARTICLE_BASE, "Vertex").unwrap();
+        let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["position", "color"]);
+        assert_eq!(fields[1].ty, AbstractType::Vec3);
+
+        let missing = get_fields_by_name(PARTICLE_BASE, "Missing");
+        assert!(matches!(missing, Err(crate::Error::DefinitionNotFound { .. })));
+        let invalid = get_fields_by_name("struct {", "Vertex");
+        assert!(matches!(invalid, Err(crate::Error::Parse { .. })));
+    }
+
+    #[test]
+    fn test_function_like_macros() {
+        let source = "
+            #define SQUARE(x) ((x) * (x))
+            struct Light { vec3 color; float radius; };
+            float area(float r) { return 3.14159 * SQUARE(r); }
+        ";
+        // Macros the definition doesn't use don't get in its way
+        let light = parse_shader_definition(source, "Light").unwrap();
+        assert_eq!(light.fields.len(), 2);
+
+        // Their arguments aren't substituted, so a size using one can't be worked out
+        let source = "#define SQUARE(x) ((x) * (x))\nstruct Grid { float cells[SQUARE(4)]; };";
+        assert!(parse_shader_definition(source, "Grid").is_err());
+    }
+
+    #[test]
+    fn test_skip_unrelated_definitions() {
+        let interface = "
+            layout(location = 0) out VsOut { flat uint id; };
+            out gl_PerVertex {
+                vec4 gl_Position;
+                float gl_ClipDistance[];
+                float gl_CullDistance[];
+            };
+            struct Light { vec3 color; };
+        ";
+        // Shader inputs and outputs have no layout, so they aren't definitions at all
+        let definitions = parse_shader(interface).unwrap();
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Light"]);
+        let missing = parse_shader_definition(interface, "VsOut");
+        assert!(matches!(missing, Err(crate::Error::DefinitionNotFound { .. })));
+
+        let source = "
+            struct Material { sampler2D albedo; };
+            struct Light { vec3 color; };
+            layout(std140) uniform Scene { Light sun; Material material; };
+        ";
+        assert!(parse_shader(source).is_err());
+        let light = parse_shader_definition(source, "Light").unwrap();
+        assert_eq!(light.fields[0].ty, AbstractType::Vec3);
+
+        // Selecting a definition which uses a broken struct reports the struct's problem
+        let scene = parse_shader_definition(source, "Scene").unwrap_err();
+        assert!(matches!(scene.root(), crate::Error::UnsupportedType { .. }));
+        assert_eq!(scene.declaration_path()[0], (DeclarationKind::Struct, "Material"));
+    }
+
+    #[test]
+    fn test_anonymous_structs() {
+        let source = "
+            struct { float weight; } unnamed;
+            struct Light { vec3 color; };
+            uniform Scene { Light sun; };
+        ";
+        // Anonymous structs can't be referred to, so they're skipped rather than failing the rest
+        let definitions = parse_shader(source).unwrap();
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Light", "Scene"]);
+        assert!(parse_shader_definition(source, "Scene").is_ok());
+
+        // Every field of every definition is still extracted
+        let mut stage = ShaderStage::parse(source).unwrap();
+        let fields = get_abstract_fields(&mut stage).unwrap();
+        let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["weight", "color", "sun"]);
+        let report = get_abstract_fields_report(&mut stage).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.fields, fields);
+    }
+
+    #[test]
+    fn test_report_every_error() {
+        let source = "
+            struct Bad {
+                vec3 position;
+                sampler2D tex;
+                float weights[count];
+                layout(shared) int flags, other[2][n];
+                vec4 color;
+            };
+        ";
+        let mut stage = ShaderStage::parse(source).unwrap();
+        let report = get_abstract_fields_report(&mut stage).unwrap();
+
+        let names: Vec<&str> = report.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["position", "color"]);
+        let bad: Vec<&str> = report.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(bad, ["tex", "weights", "flags", "other"]);
+        assert!(matches!(report.errors[0].errors[..], [crate::Error::UnsupportedType { .. }]));
+        assert!(matches!(report.errors[2].errors[..], [crate::Error::QualifiersUnsupported]));
+
+        // The first problem is the one reported when stopping early
+        let error = get_abstract_fields(&mut stage).unwrap_err();
+        let path = error.declaration_path();
+        assert_eq!(path, [(DeclarationKind::Struct, "Bad"), (DeclarationKind::Field, "tex")]);
+    }
+}
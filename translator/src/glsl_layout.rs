@@ -1,58 +1,295 @@
 use crate::abstract_data::*;
+use std::fmt;
 
-#[derive(Debug)]
+/// Which set of GLSL block layout rules to emulate
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum LayoutRule {
+    /// Uniform block rules; struct (and array) alignment is rounded up to that of a vec4
+    #[default]
+    Std140,
+    /// Storage block rules; alignment is never rounded up to 16 bytes
+    Std430,
+    /// GL_EXT_scalar_block_layout; every type aligns to its scalar component, so vectors pack tightly
+    Scalar,
+}
+
+impl fmt::Display for LayoutRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Std140 => "std140",
+            Self::Std430 => "std430",
+            Self::Scalar => "scalar",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Which layout algorithm produced a gap
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LayoutKind {
+    Glsl(LayoutRule),
+    ReprC,
+}
+
+impl fmt::Display for LayoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Glsl(rule) => write!(f, "{}", rule),
+            Self::ReprC => f.write_str("#[repr(C)]"),
+        }
+    }
+}
+
+/// Why padding was inserted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GapReason {
+    /// The next field has to start at a multiple of its alignment
+    Alignment {
+        field: String,
+        ty: AbstractType,
+        align: u64,
+        kind: LayoutKind,
+    },
+    /// The size of a struct is always a multiple of its alignment
+    StructTail { align: u64, kind: LayoutKind },
+    /// The next field has a `layout(offset = N)` qualifier
+    ExplicitOffset { field: String, offset: u64 },
+}
+
+impl fmt::Display for GapReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Alignment {
+                field,
+                ty,
+                align,
+                kind,
+            } => write!(
+                f,
+                "{} `{}` requires {}-byte alignment under {}",
+                ty, field, align, kind
+            ),
+            Self::StructTail { align, kind } => {
+                write!(f, "struct tail rounded to {} under {}", align, kind)
+            }
+            Self::ExplicitOffset { field, offset } => {
+                write!(f, "`{}` is explicitly placed at offset {}", field, offset)
+            }
+        }
+    }
+}
+
+/// A field along with the number of bytes it occupies in the layout, or a gap of padding bytes
+#[derive(Clone, Debug)]
 pub enum FieldGap {
-    Field(AbstractField),
-    Gap(u64),
+    Field(AbstractField, u64),
+    Gap(u64, GapReason),
 }
 
 impl FieldGap {
     pub fn size(&self) -> u64 {
         match self {
-            FieldGap::Gap(g) => *g,
-            FieldGap::Field(f) => f.ty.size(),
+            FieldGap::Gap(g, _) => *g,
+            FieldGap::Field(_, size) => *size,
         }
     }
+
+    /// Padding needed before `field` at `offset`, if any
+    pub(crate) fn alignment_gap(
+        offset: u64,
+        field: &AbstractField,
+        align: u64,
+        kind: LayoutKind,
+    ) -> Option<Self> {
+        let gap = compute_gap(offset, align)?;
+        let reason = GapReason::Alignment {
+            field: field.name.clone(),
+            ty: field.ty.clone(),
+            align,
+            kind,
+        };
+        Some(FieldGap::Gap(gap, reason))
+    }
+
+    /// Padding needed after the last field at `offset`, if any
+    pub(crate) fn tail_gap(offset: u64, align: u64, kind: LayoutKind) -> Option<Self> {
+        let gap = compute_gap(offset, align)?;
+        Some(FieldGap::Gap(gap, GapReason::StructTail { align, kind }))
+    }
+}
+
+/// Overall alignment of a GLSL struct holding these fields, which is also the granularity of its size
+pub fn struct_align_gl(fields: &[AbstractField], rule: LayoutRule) -> u64 {
+    let align = fields
+        .iter()
+        .map(|f| f.align_gl(rule))
+        .max()
+        .unwrap_or(1);
+    match rule {
+        LayoutRule::Std140 => round_up(align, AbstractType::Vec4.align_gl(rule)),
+        LayoutRule::Std430 | LayoutRule::Scalar => align,
+    }
 }
 
 /// Attempts to emulate glsls layout function
 /// Will produce a set of fields and gaps which will attempt to match glsls layout 
-pub fn naive_layout_glsl_only(fields: &[AbstractField]) -> Vec<FieldGap> {
+/// Explicit offsets are assumed to be valid, see `validate_explicit_layout`
+pub fn naive_layout_glsl_only(fields: &[AbstractField], rule: LayoutRule) -> Vec<FieldGap> {
+    let kind = LayoutKind::Glsl(rule);
     let mut output = Vec::new();
     let mut offset = 0;
     for field in fields {
-        if let Some(gap) = compute_gap(offset, field.ty.align_gl()) {
-            output.push(FieldGap::Gap(gap));
-            offset += gap;
+        let align = field.align_gl(rule);
+        match field.offset {
+            Some(explicit) if explicit > offset => {
+                let start = round_up(explicit, align);
+                let reason = GapReason::ExplicitOffset {
+                    field: field.name.clone(),
+                    offset: explicit,
+                };
+                output.push(FieldGap::Gap(start - offset, reason));
+                offset = start;
+            }
+            _ => {
+                if let Some(gap) = FieldGap::alignment_gap(offset, field, align, kind) {
+                    offset += gap.size();
+                    output.push(gap);
+                }
+            }
         }
-        output.push(FieldGap::Field(field.clone()));
-        offset += field.ty.size();
+        let size = field.ty.size_gl(rule);
+        output.push(FieldGap::Field(field.clone(), size));
+        offset += size;
     }
-    if let Some(gap) = compute_gap(offset, AbstractType::Vec4.align_gl()) {
-        output.push(FieldGap::Gap(gap));
+
+    // A runtime-sized array runs to the end of the buffer, so there's no tail to pad
+    if ends_in_runtime_array(fields) {
+        return output;
+    }
+
+    if let Some(gap) = FieldGap::tail_gap(offset, struct_align_gl(fields, rule), kind) {
+        output.push(gap);
     }
 
     output
 }
 
+/// Checks `layout(offset = N)` qualifiers against the layout rule
+/// Each offset must respect the alignment of the member's type, and leave room for the members before it
+pub fn validate_explicit_layout(fields: &[AbstractField], rule: LayoutRule) -> crate::Result<()> {
+    // Where the previous member ends
+    let mut end = 0;
+    let mut offset = 0;
+    for fg in &naive_layout_glsl_only(fields, rule) {
+        offset += fg.size();
+        if let FieldGap::Field(f, _) = fg {
+            if let Some(explicit) = f.offset {
+                let align = f.ty.align_gl(rule);
+                if explicit % align != 0 {
+                    return Err(crate::Error::MisalignedOffset {
+                        name: f.name.clone(),
+                        offset: explicit,
+                        align,
+                    });
+                }
+                if explicit < end {
+                    return Err(crate::Error::OverlappingOffset {
+                        name: f.name.clone(),
+                        offset: explicit,
+                        end,
+                    });
+                }
+            }
+            end = offset;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the last of these fields is a runtime-sized array
+pub fn ends_in_runtime_array(fields: &[AbstractField]) -> bool {
+    matches!(fields.last(), Some(f) if f.ty.is_runtime_array())
+}
+
+/// Whether a layout ends in a runtime-sized array
+pub fn ends_in_runtime_array_layout(fgs: &[FieldGap]) -> bool {
+    matches!(fgs.last(), Some(FieldGap::Field(f, _)) if f.ty.is_runtime_array())
+}
+
+/// Where a runtime-sized array sits within a buffer block, for sizing buffers on the host
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeArrayLayout {
+    /// Bytes used by the fixed-size members ahead of the array
+    pub header_size: u64,
+    /// Offset of the first array element, after aligning the end of the header
+    pub array_offset: u64,
+    /// Distance in bytes between consecutive array elements
+    pub stride: u64,
+}
+
+impl RuntimeArrayLayout {
+    /// Number of bytes a buffer needs to hold `len` array elements
+    pub fn buffer_size(&self, len: u64) -> u64 {
+        self.array_offset + self.stride * len
+    }
+}
+
+/// Describes the runtime-sized array ending a buffer block, if it has one
+pub fn runtime_array_layout(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+) -> Option<RuntimeArrayLayout> {
+    let elem = match &fields.last()?.ty {
+        AbstractType::RuntimeArray(elem) => elem,
+        _ => return None,
+    };
+
+    // The runtime array itself takes no space, so the layout ends where it begins
+    let mut offset = 0;
+    let mut header_size = 0;
+    for fg in &naive_layout_glsl_only(fields, rule) {
+        offset += fg.size();
+        match fg {
+            FieldGap::Field(f, _) if !f.ty.is_runtime_array() => header_size = offset,
+            _ => (),
+        }
+    }
+
+    Some(RuntimeArrayLayout {
+        header_size,
+        array_offset: offset,
+        stride: elem.array_stride_gl(rule),
+    })
+}
+
+/// Total size in bytes covered by a layout, including any trailing gap
+pub fn layout_size(fgs: &[FieldGap]) -> u64 {
+    fgs.iter().map(FieldGap::size).sum()
+}
+
 pub fn summarize_layout(fgs: &[FieldGap]) {
+    print!("{}", layout_report(fgs));
+}
+
+/// Describes a layout line by line, with the byte range of each field and the reason for each gap
+pub fn layout_report(fgs: &[FieldGap]) -> String {
+    let mut report = String::new();
     let mut offset = 0;
     for fg in fgs {
-        let size = match fg {
-            FieldGap::Gap(gap) => *gap,
-            FieldGap::Field(f) => f.ty.size(),
+        let size = fg.size();
+        // A runtime-sized array takes no space, so it gets an open-ended range
+        let range = match size {
+            0 => format!("{:2}-  ", offset),
+            _ => format!("{:2}-{:2}", offset, offset + size - 1),
         };
-        print!("{:2}-{:2}: ", offset, offset + size - 1);
-        match fg {
-            FieldGap::Gap(_) => {
-                println!("<gap> ({})", size);
-            }
-            FieldGap::Field(f) => {
-                println!("{} ({})", f.name, size);
-            }
-        }
+        let line = match fg {
+            FieldGap::Gap(_, reason) => format!("{}: <gap> ({}): {}\n", range, size, reason),
+            FieldGap::Field(f, _) => format!("{}: {} ({})\n", range, f.name, size),
+        };
+        report.push_str(&line);
         offset += size;
     }
+    report
 }
 
 pub fn compute_gap(base: u64, align: u64) -> Option<u64> {
@@ -64,3 +301,221 @@ pub fn compute_gap(base: u64, align: u64) -> Option<u64> {
         None
     }
 }
+
+/// Rounds `base` up to the next multiple of `align`
+pub fn round_up(base: u64, align: u64) -> u64 {
+    base + compute_gap(base, align).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_tail_per_rule() {
+        let fields = [
+            AbstractField::new("wonk", AbstractType::Vec2),
+            AbstractField::new("clonk", AbstractType::Float),
+        ];
+        assert_eq!(layout_size(&naive_layout_glsl_only(&fields, LayoutRule::Std140)), 16);
+        assert_eq!(layout_size(&naive_layout_glsl_only(&fields, LayoutRule::Std430)), 16);
+
+        let fields = [AbstractField::new("clonk", AbstractType::Float)];
+        assert_eq!(layout_size(&naive_layout_glsl_only(&fields, LayoutRule::Std140)), 16);
+        assert_eq!(layout_size(&naive_layout_glsl_only(&fields, LayoutRule::Std430)), 4);
+    }
+
+    #[test]
+    fn test_scalar_packs_vec3() {
+        let fields = [
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("velocity", AbstractType::Vec3),
+        ];
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Scalar);
+        assert!(layout.iter().all(|fg| matches!(fg, FieldGap::Field(..))));
+        assert_eq!(layout_size(&layout), 28);
+    }
+
+    #[test]
+    fn test_explicit_offset_and_align() {
+        let mut color = AbstractField::new("color", AbstractType::Vec4);
+        color.offset = Some(32);
+        let mut mass = AbstractField::new("mass", AbstractType::Float);
+        mass.align = Some(16);
+        let fields = [AbstractField::new("position", AbstractType::Vec3), color, mass];
+
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std430);
+        let sizes: Vec<u64> = layout.iter().map(FieldGap::size).collect();
+        assert_eq!(sizes, [12, 20, 16, 4, 12]);
+        assert!(validate_explicit_layout(&fields, LayoutRule::Std430).is_ok());
+
+        let mut fields = fields;
+        fields[1].offset = Some(0);
+        let overlapping = validate_explicit_layout(&fields, LayoutRule::Std430);
+        assert!(matches!(overlapping, Err(crate::Error::OverlappingOffset { end: 12, .. })));
+
+        fields[1].offset = Some(20);
+        let misaligned = validate_explicit_layout(&fields, LayoutRule::Std430);
+        assert!(matches!(misaligned, Err(crate::Error::MisalignedOffset { align: 16, .. })));
+    }
+
+    #[test]
+    fn test_dvec3_alignment() {
+        let fields = [
+            AbstractField::new("charge", AbstractType::Double),
+            AbstractField::new("position", AbstractType::DVec3),
+        ];
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        assert!(matches!(layout[1], FieldGap::Gap(24, _)));
+        if let FieldGap::Gap(_, reason) = &layout[1] {
+            let expected = "dvec3 `position` requires 32-byte alignment under std140";
+            assert_eq!(reason.to_string(), expected);
+        }
+        assert_eq!(layout_size(&layout), 64);
+
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Scalar);
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout_size(&layout), 32);
+    }
+
+    #[test]
+    fn test_matrix_columns() {
+        let mat = |columns, rows, row_major| {
+            AbstractType::Matrix(Matrix {
+                columns,
+                rows,
+                double: false,
+                row_major,
+            })
+        };
+
+        let mat3 = mat(3, 3, false);
+        assert_eq!(mat3.size_gl(LayoutRule::Std140), 48);
+        assert_eq!(mat3.size_gl(LayoutRule::Std430), 48);
+        assert_eq!(mat3.size_gl(LayoutRule::Scalar), 36);
+        assert_eq!(mat3.align_gl(LayoutRule::Std140), 16);
+        assert_eq!(mat3.rust_type(LayoutRule::Std140), "[[f32; 4]; 3]");
+
+        let mat2 = mat(2, 2, false);
+        assert_eq!(mat2.size_gl(LayoutRule::Std140), 32);
+        assert_eq!(mat2.size_gl(LayoutRule::Std430), 16);
+        assert_eq!(mat2.align_gl(LayoutRule::Std430), 8);
+
+        // Two columns of vec3 versus three rows of vec2
+        assert_eq!(mat(2, 3, false).size_gl(LayoutRule::Std430), 32);
+        assert_eq!(mat(2, 3, true).size_gl(LayoutRule::Std430), 24);
+    }
+
+    #[test]
+    fn test_array_stride() {
+        let floats = AbstractType::Array(Box::new(AbstractType::Float), 4);
+        assert_eq!(floats.size_gl(LayoutRule::Std140), 64);
+        assert_eq!(floats.size_gl(LayoutRule::Std430), 16);
+        assert_eq!(floats.rust_type(LayoutRule::Std140), "[[f32; 4]; 4]");
+        assert_eq!(floats.rust_type(LayoutRule::Std430), "[f32; 4]");
+
+        let vecs = AbstractType::Array(Box::new(AbstractType::Vec3), 2);
+        assert_eq!(vecs.size_gl(LayoutRule::Std430), 32);
+        assert_eq!(vecs.size_gl(LayoutRule::Scalar), 24);
+        assert_eq!(vecs.to_string(), "vec3[2]");
+    }
+
+    #[test]
+    fn test_nested_struct() {
+        let inner = AbstractType::Struct(AbstractStruct {
+            name: "Inner".into(),
+            fields: vec![AbstractField::new("x", AbstractType::Float)],
+        });
+        let fields = [
+            AbstractField::new("a", AbstractType::Float),
+            AbstractField::new("b", inner.clone()),
+        ];
+
+        assert_eq!(inner.align_gl(LayoutRule::Std140), 16);
+        assert_eq!(inner.size_gl(LayoutRule::Std140), 16);
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        assert!(matches!(layout[1], FieldGap::Gap(12, _)));
+        assert_eq!(layout_size(&layout), 32);
+
+        assert_eq!(inner.size_gl(LayoutRule::Std430), 4);
+        assert_eq!(layout_size(&naive_layout_glsl_only(&fields, LayoutRule::Std430)), 8);
+        assert_eq!(inner.rust_type(LayoutRule::Std430), "Inner");
+    }
+
+    #[test]
+    fn test_runtime_array() {
+        let fields = [
+            AbstractField::new("header", AbstractType::Vec4),
+            AbstractField::new("count", AbstractType::Float),
+            AbstractField::new("data", AbstractType::RuntimeArray(Box::new(AbstractType::Float))),
+        ];
+
+        let std430 = runtime_array_layout(&fields, LayoutRule::Std430).unwrap();
+        assert_eq!(std430.header_size, 20);
+        assert_eq!(std430.array_offset, 20);
+        assert_eq!(std430.stride, 4);
+        assert_eq!(std430.buffer_size(8), 52);
+
+        let std140 = runtime_array_layout(&fields, LayoutRule::Std140).unwrap();
+        assert_eq!(std140.header_size, 20);
+        assert_eq!(std140.array_offset, 32);
+        assert_eq!(std140.stride, 16);
+
+        assert!(runtime_array_layout(&fields[..2], LayoutRule::Std430).is_none());
+    }
+
+    #[test]
+    fn test_vec3_alignment() {
+        let fields = [
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("position", AbstractType::Vec3),
+        ];
+        for &rule in &[LayoutRule::Std140, LayoutRule::Std430] {
+            let layout = naive_layout_glsl_only(&fields, rule);
+            assert!(matches!(layout[1], FieldGap::Gap(12, _)));
+            assert_eq!(layout_size(&layout), 32);
+        }
+    }
+
+    #[test]
+    fn test_integer_and_bool_types() {
+        // GLSL bools take up 32 bits in buffers, so they're mirrored by u32 rather than Rust's bool
+        for &rule in &[LayoutRule::Std140, LayoutRule::Std430, LayoutRule::Scalar] {
+            assert_eq!(AbstractType::Bool.size_gl(rule), 4);
+            assert_eq!(AbstractType::Bool.align_gl(rule), 4);
+        }
+        assert_eq!(AbstractType::Bool.rust_type(LayoutRule::Std430), "u32");
+        assert_eq!(AbstractType::BVec3.rust_type(LayoutRule::Std430), "[u32; 3]");
+        assert_eq!(AbstractType::IVec3.rust_type(LayoutRule::Std430), "[i32; 3]");
+        assert_eq!(AbstractType::Bool.align_c(), 4);
+
+        // Three component vectors align like four component ones, whatever their component type
+        for ty in [AbstractType::IVec3, AbstractType::UVec3, AbstractType::BVec3] {
+            let fields = [
+                AbstractField::new("count", AbstractType::Int),
+                AbstractField::new("v", ty.clone()),
+                AbstractField::new("flag", AbstractType::Bool),
+            ];
+            for &rule in &[LayoutRule::Std140, LayoutRule::Std430] {
+                let layout = naive_layout_glsl_only(&fields, rule);
+                assert!(matches!(layout[1], FieldGap::Gap(12, _)), "{} under {}", ty, rule);
+                assert!(matches!(layout[3], FieldGap::Field(_, 4)));
+                assert_eq!(layout_size(&layout), 32);
+            }
+            let layout = naive_layout_glsl_only(&fields, LayoutRule::Scalar);
+            assert_eq!(layout_size(&layout), 20);
+        }
+
+        let fields = [
+            AbstractField::new("flag", AbstractType::Bool),
+            AbstractField::new("mask", AbstractType::BVec2),
+            AbstractField::new("index", AbstractType::UVec4),
+        ];
+        let sizes: Vec<u64> = naive_layout_glsl_only(&fields, LayoutRule::Std430)
+            .iter()
+            .map(FieldGap::size)
+            .collect();
+        assert_eq!(sizes, [4, 4, 8, 16]);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::abstract_data::*;
+use crate::c_layout::explicit_offsets_c_mapped;
+use crate::glsl_layout::*;
+use crate::optimize::*;
+use crate::type_map::TypeMap;
+use crate::Result;
+
+/// Layouts of the Rust and GLSL sides of one struct, with identical byte offsets
+/// Each gap in the Rust layout stands for an explicit padding field
+#[derive(Debug)]
+pub struct JointLayout {
+    pub rust: Vec<FieldGap>,
+    pub glsl: Vec<FieldGap>,
+}
+
+/// Chooses a single field order and padding placement which is valid both under `#[repr(C)]` and the given GLSL rule
+///
+/// The GLSL side is the stricter of the two (every GLSL alignment is a multiple of the Rust one),
+/// so the order minimizing GLSL padding is found first. The Rust side then mirrors it, with each
+/// GLSL gap becoming an explicit padding field, and is checked to land on the same offsets.
+pub fn solve_joint_layout(fields: &[AbstractField], rule: LayoutRule) -> Result<JointLayout> {
+    let glsl = optimize_layout_glsl(fields, rule);
+    let rust = mirror_layout_c(&glsl)?;
+    Ok(JointLayout { rust, glsl })
+}
+
+/// Like `solve_joint_layout`, but only considers field orders satisfying the constraints
+pub fn solve_joint_layout_constrained(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+) -> Result<JointLayout> {
+    solve_joint_layout_mapped(fields, rule, constraints, &TypeMap::default())
+}
+
+/// Like `solve_joint_layout_constrained`, with the Rust side using the types from a type map
+pub fn solve_joint_layout_mapped(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+    map: &TypeMap,
+) -> Result<JointLayout> {
+    let glsl = match optimize_layout_glsl_mapped(fields, rule, constraints, map) {
+        Ok(glsl) => glsl,
+        // Mirroring the best GLSL order anyway points out which field the mapped types don't fit
+        Err(crate::Error::UnsatisfiableConstraints) => {
+            optimize_layout_glsl_constrained(fields, rule, constraints)?
+        }
+        Err(e) => return Err(e),
+    };
+    let rust = mirror_layout_c_mapped(&glsl, map)?;
+    Ok(JointLayout { rust, glsl })
+}
+
+/// Builds the Rust side of a GLSL layout, with every gap as an explicit padding field
+/// Fails if the Rust struct would place any field at a different offset
+pub fn mirror_layout_c(glsl: &[FieldGap]) -> Result<Vec<FieldGap>> {
+    mirror_layout_c_mapped(glsl, &TypeMap::default())
+}
+
+/// Like `mirror_layout_c`, with fields of mapped types using the mapped type's size and alignment
+/// A mapped type larger than its GLSL counterpart (like `glam::Vec3A` for a vec3) takes up the padding after it
+pub fn mirror_layout_c_mapped(glsl: &[FieldGap], map: &TypeMap) -> Result<Vec<FieldGap>> {
+    let mut rust = Vec::with_capacity(glsl.len());
+    // Bytes of the following padding already covered by the previous field, and that field
+    let mut overrun: Option<(&AbstractField, u64)> = None;
+    for fg in glsl {
+        match fg {
+            FieldGap::Gap(g, reason) => {
+                let covered = match overrun.take() {
+                    Some((f, bytes)) if bytes > *g => return Err(mapped_mismatch(f, map)),
+                    Some((_, bytes)) => bytes,
+                    None => 0,
+                };
+                if *g > covered {
+                    rust.push(FieldGap::Gap(g - covered, reason.clone()));
+                }
+            }
+            FieldGap::Field(f, size) => {
+                if let Some((prev, _)) = overrun {
+                    return Err(mapped_mismatch(prev, map));
+                }
+                let rust_size = match map.get(&f.ty) {
+                    // Padding inside a GLSL type can't be recovered from a smaller mapped type
+                    Some(mapped) if mapped.size < *size => return Err(mapped_mismatch(f, map)),
+                    Some(mapped) => mapped.size,
+                    None => *size,
+                };
+                if rust_size > *size {
+                    overrun = Some((f, rust_size - size));
+                }
+                rust.push(FieldGap::Field(f.clone(), rust_size));
+            }
+        }
+    }
+    if let Some((f, _)) = overrun {
+        return Err(mapped_mismatch(f, map));
+    }
+
+    // Rust mirrors of GLSL types are padded out to the same size, so only offsets can differ
+    let (rust_offsets, rust_size) = explicit_offsets_c_mapped(&rust, map);
+    let glsl_offsets = field_offsets(glsl);
+    let rust_fields = rust.iter().zip(rust_offsets).filter_map(|(fg, offset)| match fg {
+        FieldGap::Field(f, _) => Some((f, offset)),
+        FieldGap::Gap(..) => None,
+    });
+    for ((f, rust_offset), glsl_offset) in rust_fields.zip(glsl_offsets) {
+        if rust_offset != glsl_offset {
+            return Err(crate::Error::JointLayoutMismatch {
+                name: f.name.clone(),
+            });
+        }
+    }
+
+    // Rust may still round the size up to its own alignment
+    let glsl_size = layout_size(glsl);
+    if !ends_in_runtime_array_layout(glsl) && rust_size != glsl_size {
+        return Err(crate::Error::JointSizeMismatch {
+            rust: rust_size,
+            glsl: glsl_size,
+        });
+    }
+
+    Ok(rust)
+}
+
+/// Offsets of the fields (but not the gaps) of a layout
+fn field_offsets(fgs: &[FieldGap]) -> Vec<u64> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    for fg in fgs {
+        if let FieldGap::Field(..) = fg {
+            offsets.push(offset);
+        }
+        offset += fg.size();
+    }
+    offsets
+}
+
+fn mapped_mismatch(field: &AbstractField, map: &TypeMap) -> crate::Error {
+    crate::Error::MappedTypeMismatch {
+        name: field.name.clone(),
+        path: map.rust_type(&field.ty, LayoutRule::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c_layout::explicit_offsets_c;
+
+    #[test]
+    fn test_readme_vertex() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec3),
+        ];
+        let joint = solve_joint_layout(&fields, LayoutRule::Std140).unwrap();
+
+        // Both vec3s need a 4 byte shim after them
+        let gaps: Vec<u64> = joint
+            .rust
+            .iter()
+            .filter_map(|fg| match fg {
+                FieldGap::Gap(g, _) => Some(*g),
+                FieldGap::Field(..) => None,
+            })
+            .collect();
+        assert_eq!(gaps, [4, 4]);
+        assert_eq!(explicit_offsets_c(&joint.rust).1, layout_size(&joint.glsl));
+    }
+
+    #[test]
+    fn test_mapped_types() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec3),
+        ];
+        let constraints = LayoutConstraints::default();
+
+        // Vec3A covers the padding after each vec3 itself
+        let map = TypeMap::glam().insert(AbstractType::Vec3, "glam::Vec3A", 16, 16);
+        let joint = solve_joint_layout_mapped(&fields, LayoutRule::Std140, &constraints, &map);
+        let joint = joint.unwrap();
+        assert!(joint.rust.iter().all(|fg| matches!(fg, FieldGap::Field(_, 16))));
+
+        // ...but can't share its last 4 bytes with a float, so the float goes first instead
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("mass", AbstractType::Float),
+        ];
+        let joint = solve_joint_layout_mapped(&fields, LayoutRule::Std140, &constraints, &map);
+        let joint = joint.unwrap();
+        let names: Vec<&str> = joint
+            .rust
+            .iter()
+            .filter_map(|fg| match fg {
+                FieldGap::Field(f, _) => Some(f.name.as_str()),
+                FieldGap::Gap(..) => None,
+            })
+            .collect();
+        assert_eq!(names, ["mass", "position"]);
+
+        // Pinning the order leaves nothing for the optimizer to work around
+        let pinned = LayoutConstraints::new().pin_index("position", 0);
+        let joint = solve_joint_layout_mapped(&fields, LayoutRule::Std140, &pinned, &map);
+        assert!(matches!(joint, Err(crate::Error::MappedTypeMismatch { .. })));
+
+        // A vec4 is only 4 byte aligned under the scalar rule
+        let fields = [
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("color", AbstractType::Vec4),
+        ];
+        let joint = solve_joint_layout_mapped(&fields, LayoutRule::Scalar, &constraints, &map);
+        assert!(matches!(joint, Err(crate::Error::JointLayoutMismatch { .. })));
+    }
+
+    #[test]
+    fn test_nested_mapped_struct() {
+        let mat2 = AbstractType::Matrix(Matrix {
+            columns: 2,
+            rows: 2,
+            double: false,
+            row_major: false,
+        });
+        let s = AbstractType::Struct(AbstractStruct {
+            name: "S".into(),
+            fields: vec![AbstractField::new("m", mat2)],
+        });
+        let map = TypeMap::glam();
+        // S holds a glam::Mat2, so it's 16 byte aligned in Rust even though std430 only needs 8
+        assert_eq!(map.align_c(&s), 16);
+        assert_eq!(map.size_c(&s, LayoutRule::Std430), 16);
+
+        let fields = [
+            AbstractField::new("a", AbstractType::Float),
+            AbstractField::new("s", s),
+            AbstractField::new("b", AbstractType::Float),
+            AbstractField::new("c", AbstractType::Float),
+        ];
+        let glsl = naive_layout_glsl_only(&fields, LayoutRule::Std430);
+        let mirrored = mirror_layout_c_mapped(&glsl, &map);
+        assert!(matches!(mirrored, Err(crate::Error::JointLayoutMismatch { .. })));
+
+        // Reordering moves S to an offset Rust agrees with
+        let constraints = LayoutConstraints::default();
+        let joint = solve_joint_layout_mapped(&fields, LayoutRule::Std430, &constraints, &map);
+        let joint = joint.unwrap();
+        let names = joint.glsl.iter().filter_map(|fg| match fg {
+            FieldGap::Field(f, _) => Some(f.name.as_str()),
+            FieldGap::Gap(..) => None,
+        });
+        let offsets: Vec<(&str, u64)> = names.zip(field_offsets(&joint.glsl)).collect();
+        assert!(offsets.iter().any(|&(name, offset)| name == "s" && offset % 16 == 0));
+        assert_eq!(explicit_offsets_c_mapped(&joint.rust, &map).1, layout_size(&joint.glsl));
+    }
+}
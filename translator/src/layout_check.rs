@@ -0,0 +1,686 @@
+use crate::glsl_layout::{ends_in_runtime_array_layout, layout_size, round_up, FieldGap};
+use crate::rust_codegen::rust_field_name;
+use crate::type_map::TypeMap;
+use crate::Result;
+use std::fmt;
+
+/// A field of a Rust struct, placed by the `#[repr(C)]` layout algorithm
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RustField {
+    pub name: String,
+    /// The type as written, e.g. `[f32; 3]`
+    pub ty: String,
+    pub offset: u64,
+    /// Zero for a trailing slice
+    pub size: u64,
+}
+
+/// A `#[repr(C)]` struct parsed from Rust source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RustStruct {
+    pub name: String,
+    pub fields: Vec<RustField>,
+    pub size: u64,
+    pub align: u64,
+}
+
+/// A difference between a Rust struct and the GLSL layout it should mirror
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    Offset { field: String, rust: u64, glsl: u64 },
+    Size { field: String, rust: u64, glsl: u64 },
+    /// A GLSL field without a Rust field of the same name
+    MissingInRust { field: String },
+    /// A Rust field (other than padding, whose name starts with `_`) without a GLSL field of the same name
+    MissingInGlsl { field: String },
+    StructSize { rust: u64, glsl: u64 },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Offset { field, rust, glsl } => write!(
+                f,
+                "`{}` is at offset {} in Rust, but {} in GLSL",
+                field, rust, glsl
+            ),
+            Self::Size { field, rust, glsl } => write!(
+                f,
+                "`{}` is {} bytes in Rust, but {} bytes in GLSL",
+                field, rust, glsl
+            ),
+            Self::MissingInRust { field } => {
+                write!(f, "`{}` is missing from the Rust struct", field)
+            }
+            Self::MissingInGlsl { field } => write!(f, "`{}` isn't in the GLSL struct", field),
+            Self::StructSize { rust, glsl } => write!(
+                f,
+                "The Rust struct is {} bytes, but the GLSL struct is {} bytes",
+                rust, glsl
+            ),
+        }
+    }
+}
+
+/// Parses the struct `name` out of Rust source and lays it out as `#[repr(C)]`
+/// Fields may be primitives, arrays, types from `map`, or other structs defined in the same source
+pub fn parse_rust_struct(source: &str, name: &str, map: &TypeMap) -> Result<RustStruct> {
+    let tokens: Vec<Token> = Tokens::new(source).collect();
+    let decls = parse_declarations(&tokens)?;
+    let layouts = Layouts {
+        decls: &decls,
+        map,
+    };
+    layouts.layout_struct(name, &mut Vec::new())
+}
+
+/// Compares a Rust struct field-by-field with a GLSL layout, listing every difference
+/// Fields are matched up by name, and fields of struct type are only compared by size
+/// A Rust field may be larger than its GLSL field if it only covers the padding after it,
+/// like a `glam::Vec3A` for a vec3 followed by a vec4
+pub fn compare_layouts(rust: &RustStruct, glsl: &[FieldGap]) -> Vec<LayoutMismatch> {
+    let mut mismatches = Vec::new();
+    let mut next = 0;
+    for (idx, fg) in glsl.iter().enumerate() {
+        let offset = next;
+        next += fg.size();
+        let (f, size) = match fg {
+            FieldGap::Field(f, size) => (f, *size),
+            FieldGap::Gap(..) => continue,
+        };
+        // Padding between this field and the next one, or the end of the struct
+        let padding: u64 = glsl[idx + 1..]
+            .iter()
+            .take_while(|fg| matches!(fg, FieldGap::Gap(..)))
+            .map(FieldGap::size)
+            .sum();
+        let rust_name = rust_field_name(&f.name);
+        let r = match rust.fields.iter().find(|r| r.name == rust_name) {
+            Some(r) => r,
+            None => {
+                mismatches.push(LayoutMismatch::MissingInRust {
+                    field: f.name.clone(),
+                });
+                continue;
+            }
+        };
+        if r.offset != offset {
+            mismatches.push(LayoutMismatch::Offset {
+                field: f.name.clone(),
+                rust: r.offset,
+                glsl: offset,
+            });
+        }
+        let covers_padding = r.offset == offset && r.size > size && r.size <= size + padding;
+        if r.size != size && !covers_padding {
+            mismatches.push(LayoutMismatch::Size {
+                field: f.name.clone(),
+                rust: r.size,
+                glsl: size,
+            });
+        }
+    }
+
+    for r in &rust.fields {
+        let in_glsl = glsl
+            .iter()
+            .any(|fg| matches!(fg, FieldGap::Field(f, _) if rust_field_name(&f.name) == r.name));
+        if !in_glsl && !r.name.starts_with('_') {
+            mismatches.push(LayoutMismatch::MissingInGlsl {
+                field: r.name.clone(),
+            });
+        }
+    }
+
+    // A runtime-sized array has no fixed size to compare against
+    let glsl_size = layout_size(glsl);
+    if !ends_in_runtime_array_layout(glsl) && rust.size != glsl_size {
+        mismatches.push(LayoutMismatch::StructSize {
+            rust: rust.size,
+            glsl: glsl_size,
+        });
+    }
+    mismatches
+}
+
+/// Parses the Rust struct `name` and compares it with a GLSL layout, e.g. from `naive_layout_glsl_only`
+pub fn check_rust_struct(
+    source: &str,
+    name: &str,
+    glsl: &[FieldGap],
+    map: &TypeMap,
+) -> Result<Vec<LayoutMismatch>> {
+    let rust = parse_rust_struct(source, name, map)?;
+    Ok(compare_layouts(&rust, glsl))
+}
+
+fn parse_error<T>(message: String) -> Result<T> {
+    Err(crate::Error::RustParse { message })
+}
+
+/// A type as written in a field declaration
+enum TypeExpr {
+    Path(String),
+    Array(Box<TypeExpr>, u64),
+    Slice(Box<TypeExpr>),
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Path(path) => f.write_str(path),
+            Self::Array(elem, len) => write!(f, "[{}; {}]", elem, len),
+            Self::Slice(elem) => write!(f, "[{}]", elem),
+        }
+    }
+}
+
+/// A struct declaration along with the parts of its `#[repr]` attribute we understand
+struct StructDecl {
+    name: String,
+    repr_c: bool,
+    packed: bool,
+    align: Option<u64>,
+    fields: Vec<(String, TypeExpr)>,
+}
+
+/// Finds every struct with named fields, skipping tuple, unit and generic structs
+fn parse_declarations(tokens: &[Token]) -> Result<Vec<StructDecl>> {
+    let mut decls = Vec::new();
+    let mut attributes = Vec::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        match tokens[idx] {
+            Token::Punct("#") => {
+                let (attribute, end) = attribute(tokens, idx)?;
+                attributes.push(attribute);
+                idx = end;
+                continue;
+            }
+            Token::Ident("pub") => {
+                idx = skip_visibility(tokens, idx);
+                continue;
+            }
+            Token::Ident("struct") => {
+                if let (Some(&Token::Ident(name)), Some(Token::Punct("{"))) =
+                    (tokens.get(idx + 1), tokens.get(idx + 2))
+                {
+                    let (fields, end) = parse_fields(tokens, idx + 3)?;
+                    let mut decl = StructDecl {
+                        name: name.to_string(),
+                        repr_c: false,
+                        packed: false,
+                        align: None,
+                        fields,
+                    };
+                    for attribute in &attributes {
+                        read_repr(attribute, &mut decl)?;
+                    }
+                    decls.push(decl);
+                    idx = end;
+                }
+            }
+            _ => (),
+        }
+        attributes.clear();
+        idx += 1;
+    }
+    Ok(decls)
+}
+
+/// The tokens inside the attribute starting at `idx`, and the index just past it
+fn attribute<'a, 't>(tokens: &'a [Token<'t>], idx: usize) -> Result<(&'a [Token<'t>], usize)> {
+    let mut open = idx + 1;
+    // Inner attributes like #![allow(..)] don't apply to the next item, but are skipped the same way
+    if tokens.get(open) == Some(&Token::Punct("!")) {
+        open += 1;
+    }
+    if tokens.get(open) != Some(&Token::Punct("[")) {
+        return parse_error("Expected [ after #".into());
+    }
+    let close = matching(tokens, open)?;
+    Ok((&tokens[open + 1..close], close + 1))
+}
+
+/// Index of the bracket closing the one at `open`
+fn matching(tokens: &[Token], open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (idx, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Punct("(") | Token::Punct("[") | Token::Punct("{") => depth += 1,
+            Token::Punct(")") | Token::Punct("]") | Token::Punct("}") => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(idx);
+                }
+            }
+            _ => (),
+        }
+    }
+    parse_error(format!("Unclosed {}", tokens[open].text()))
+}
+
+/// Index just past `pub` or `pub(...)`
+fn skip_visibility(tokens: &[Token], idx: usize) -> usize {
+    if tokens.get(idx + 1) == Some(&Token::Punct("(")) {
+        matching(tokens, idx + 1).map_or(tokens.len(), |close| close + 1)
+    } else {
+        idx + 1
+    }
+}
+
+/// Reads `#[repr(C)]`, `#[repr(C, align(N))]` and `#[repr(packed)]`, ignoring any other attribute
+fn read_repr(attribute: &[Token], decl: &mut StructDecl) -> Result<()> {
+    if attribute.first() != Some(&Token::Ident("repr")) {
+        return Ok(());
+    }
+    let mut idx = 1;
+    while idx < attribute.len() {
+        match attribute[idx] {
+            Token::Ident("C") => decl.repr_c = true,
+            Token::Ident("packed") => decl.packed = true,
+            Token::Ident("align") => {
+                if let Some(Token::Number(n)) = attribute.get(idx + 2) {
+                    decl.align = Some(number(n)?);
+                    idx += 3;
+                }
+            }
+            _ => (),
+        }
+        idx += 1;
+    }
+    Ok(())
+}
+
+/// Parses the fields of a struct body starting at `idx`, returning them and the index of the closing brace
+fn parse_fields(tokens: &[Token], mut idx: usize) -> Result<(Vec<(String, TypeExpr)>, usize)> {
+    let mut fields = Vec::new();
+    loop {
+        match tokens.get(idx) {
+            Some(Token::Punct("}")) => return Ok((fields, idx)),
+            Some(Token::Punct("#")) => idx = attribute(tokens, idx)?.1,
+            Some(Token::Ident("pub")) => idx = skip_visibility(tokens, idx),
+            Some(&Token::Ident(name)) => {
+                if tokens.get(idx + 1) != Some(&Token::Punct(":")) {
+                    return parse_error(format!("Expected : after field {}", name));
+                }
+                idx += 2;
+                let ty = parse_type(tokens, &mut idx)?;
+                let name = name.strip_prefix("r#").unwrap_or(name);
+                fields.push((name.to_string(), ty));
+                match tokens.get(idx) {
+                    Some(Token::Punct(",")) => idx += 1,
+                    Some(Token::Punct("}")) => (),
+                    _ => return parse_error(format!("Expected , after field {}", name)),
+                }
+            }
+            Some(token) => return parse_error(format!("Unexpected {} in struct", token.text())),
+            None => return parse_error("Unclosed struct".into()),
+        }
+    }
+}
+
+/// Parses a type starting at `idx`, leaving `idx` just past it
+fn parse_type(tokens: &[Token], idx: &mut usize) -> Result<TypeExpr> {
+    if tokens.get(*idx) == Some(&Token::Punct("[")) {
+        *idx += 1;
+        let elem = Box::new(parse_type(tokens, idx)?);
+        let ty = match (tokens.get(*idx), tokens.get(*idx + 1)) {
+            (Some(Token::Punct(";")), Some(Token::Number(n))) => {
+                *idx += 2;
+                TypeExpr::Array(elem, number(n)?)
+            }
+            (Some(Token::Punct("]")), _) => TypeExpr::Slice(elem),
+            _ => return parse_error("Only arrays with a literal length are supported".into()),
+        };
+        if tokens.get(*idx) != Some(&Token::Punct("]")) {
+            return parse_error("Expected ] after array type".into());
+        }
+        *idx += 1;
+        return Ok(ty);
+    }
+
+    // Anything else is a path, possibly with generic arguments, running up to the end of the field
+    let mut path = String::new();
+    let mut depth = 0;
+    while let Some(token) = tokens.get(*idx) {
+        match token {
+            Token::Punct("<") => depth += 1,
+            Token::Punct(">") => depth -= 1,
+            Token::Punct(",") | Token::Punct(";") | Token::Punct("]") | Token::Punct("}")
+                if depth == 0 =>
+            {
+                break
+            }
+            _ => (),
+        }
+        path.push_str(token.text());
+        *idx += 1;
+    }
+    if path.is_empty() {
+        return parse_error("Expected a type".into());
+    }
+    Ok(TypeExpr::Path(path))
+}
+
+/// The value of an integer literal, like `4`, `0x10` or `16usize`
+fn number(literal: &str) -> Result<u64> {
+    let digits: String = literal.chars().filter(|&c| c != '_').collect();
+    let (digits, radix) = match digits.strip_prefix("0x") {
+        Some(hex) => (hex.to_string(), 16),
+        None => (digits, 10),
+    };
+    let end = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    u64::from_str_radix(&digits[..end], radix)
+        .or_else(|_| parse_error(format!("Invalid number {}", literal)))
+}
+
+/// Lays out struct declarations, looking field types up in a type map or among the other structs
+struct Layouts<'a> {
+    decls: &'a [StructDecl],
+    map: &'a TypeMap,
+}
+
+impl Layouts<'_> {
+    /// `stack` holds the structs being laid out, to catch a struct containing itself
+    fn layout_struct(&self, name: &str, stack: &mut Vec<String>) -> Result<RustStruct> {
+        let decl = match self.decls.iter().find(|d| d.name == name) {
+            Some(decl) => decl,
+            None => return parse_error(format!("No struct named {}", name)),
+        };
+        if !decl.repr_c {
+            return Err(crate::Error::NotReprC { name: name.into() });
+        }
+        if decl.packed {
+            return parse_error(format!("Struct {} is packed, which isn't supported", name));
+        }
+        if stack.iter().any(|s| s == name) {
+            return parse_error(format!("Struct {} contains itself", name));
+        }
+        stack.push(name.into());
+
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        let mut align = decl.align.unwrap_or(1);
+        for (field, ty) in &decl.fields {
+            let (size, field_align) = self.layout_type(field, ty, stack)?;
+            offset = round_up(offset, field_align);
+            align = align.max(field_align);
+            fields.push(RustField {
+                name: field.clone(),
+                ty: ty.to_string(),
+                offset,
+                size,
+            });
+            offset += size;
+        }
+        stack.pop();
+
+        Ok(RustStruct {
+            name: name.into(),
+            fields,
+            size: round_up(offset, align),
+            align,
+        })
+    }
+
+    /// Size and alignment of a field's type
+    fn layout_type(
+        &self,
+        field: &str,
+        ty: &TypeExpr,
+        stack: &mut Vec<String>,
+    ) -> Result<(u64, u64)> {
+        match ty {
+            TypeExpr::Array(elem, len) => {
+                let (size, align) = self.layout_type(field, elem, stack)?;
+                Ok((size * len, align))
+            }
+            TypeExpr::Slice(elem) => {
+                let (_, align) = self.layout_type(field, elem, stack)?;
+                Ok((0, align))
+            }
+            TypeExpr::Path(path) => {
+                if let Some(layout) = primitive(path) {
+                    return Ok(layout);
+                }
+                if let Some(mapped) = self.map.find_path(path) {
+                    return Ok((mapped.size, mapped.align));
+                }
+                let name = path.rsplit("::").next().unwrap_or(path);
+                if self.decls.iter().any(|d| d.name == name) {
+                    let s = self.layout_struct(name, stack)?;
+                    return Ok((s.size, s.align));
+                }
+                Err(crate::Error::UnknownRustType {
+                    name: field.into(),
+                    ty: path.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Size and alignment of a primitive type (as on x86-64)
+fn primitive(path: &str) -> Option<(u64, u64)> {
+    let name = path.trim_start_matches("::");
+    let name = name
+        .strip_prefix("std::primitive::")
+        .or_else(|| name.strip_prefix("core::primitive::"))
+        .unwrap_or(name);
+    let size = match name {
+        "u8" | "i8" | "bool" => 1,
+        "u16" | "i16" => 2,
+        "u32" | "i32" | "f32" | "char" => 4,
+        "u64" | "i64" | "f64" | "usize" | "isize" => 8,
+        "u128" | "i128" => 16,
+        _ => return None,
+    };
+    Some((size, size))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Token<'t> {
+    Ident(&'t str),
+    Number(&'t str),
+    Punct(&'t str),
+}
+
+impl<'t> Token<'t> {
+    fn text(&self) -> &'t str {
+        match *self {
+            Token::Ident(t) | Token::Number(t) | Token::Punct(t) => t,
+        }
+    }
+}
+
+/// Splits Rust source into identifiers, numbers and single-character punctuation, skipping comments,
+/// string and character literals
+struct Tokens<'t> {
+    rest: &'t str,
+}
+
+impl<'t> Tokens<'t> {
+    fn new(source: &'t str) -> Self {
+        Self { rest: source }
+    }
+
+    /// Skips whitespace, comments and literals, returning whether any were skipped
+    fn skip_ignored(&mut self) -> bool {
+        let trimmed = self.rest.trim_start();
+        let skipped = trimmed.len() != self.rest.len();
+        self.rest = trimmed;
+
+        let end = if self.rest.starts_with("//") {
+            self.rest.find('\n').unwrap_or(self.rest.len())
+        } else if self.rest.starts_with("/*") {
+            self.rest.find("*/").map_or(self.rest.len(), |idx| idx + 2)
+        } else if self.rest.starts_with('"') {
+            literal_len(self.rest, '"')
+        } else if (self.rest.starts_with('\'') && self.rest[1..].chars().nth(1) == Some('\''))
+            || self.rest.starts_with("'\\")
+        {
+            literal_len(self.rest, '\'')
+        } else {
+            return skipped;
+        };
+        self.rest = &self.rest[end..];
+        true
+    }
+}
+
+/// Length of a string or character literal, including its quotes
+fn literal_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (idx, c) in text.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == quote && !escaped => return idx + 1,
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
+
+impl<'t> Iterator for Tokens<'t> {
+    type Item = Token<'t>;
+
+    fn next(&mut self) -> Option<Token<'t>> {
+        while self.skip_ignored() {}
+        let first = self.rest.chars().next()?;
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        // Raw identifiers keep their prefix, which the field parser strips
+        let start = if self.rest.starts_with("r#") { 2 } else { 0 };
+        let len = if is_word(first) {
+            start + self.rest[start..].find(|c| !is_word(c)).unwrap_or(self.rest.len() - start)
+        } else {
+            first.len_utf8()
+        };
+        let (text, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(if first.is_ascii_digit() {
+            Token::Number(text)
+        } else if is_word(first) {
+            Token::Ident(text)
+        } else {
+            Token::Punct(text)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abstract_data::{AbstractField, AbstractType};
+    use crate::glsl_layout::{naive_layout_glsl_only, LayoutRule};
+
+    const VERTEX: &str = "
+        /// A vertex, mirroring the GLSL struct
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        pub struct Vertex {
+            pub position: [f32; 3],
+            _pad0: [u8; 4],
+            pub(crate) color: glam::Vec3,
+            _pad1: [u8; 4],
+        }
+
+        // Not repr(C), so its layout is up to rustc
+        struct Loose { a: u8, b: u32 }
+    ";
+
+    #[test]
+    fn test_parse_rust_struct() {
+        let vertex = parse_rust_struct(VERTEX, "Vertex", &TypeMap::glam()).unwrap();
+        let offsets: Vec<u64> = vertex.fields.iter().map(|f| f.offset).collect();
+        assert_eq!(offsets, [0, 12, 16, 28]);
+        assert_eq!(vertex.fields[0].ty, "[f32; 3]");
+        assert_eq!(vertex.size, 32);
+
+        let unmapped = parse_rust_struct(VERTEX, "Vertex", &TypeMap::new());
+        assert!(matches!(unmapped, Err(crate::Error::UnknownRustType { .. })));
+        let loose = parse_rust_struct(VERTEX, "Loose", &TypeMap::new());
+        assert!(matches!(loose, Err(crate::Error::NotReprC { .. })));
+    }
+
+    #[test]
+    fn test_compare_layouts() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec3),
+        ];
+        let glsl = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        let matching = check_rust_struct(VERTEX, "Vertex", &glsl, &TypeMap::glam()).unwrap();
+        assert_eq!(matching, []);
+
+        let source = "
+            #[repr(C, align(8))]
+            struct Vertex { position: [f32; 3], color: [f32; 4], r#type: u32 }
+        ";
+        let mismatches = check_rust_struct(source, "Vertex", &glsl, &TypeMap::new()).unwrap();
+        let expected = [
+            LayoutMismatch::Offset {
+                field: "color".into(),
+                rust: 12,
+                glsl: 16,
+            },
+            LayoutMismatch::Size {
+                field: "color".into(),
+                rust: 16,
+                glsl: 12,
+            },
+            LayoutMismatch::MissingInGlsl {
+                field: "type".into(),
+            },
+        ];
+        assert_eq!(mismatches, expected);
+
+        let source = "#[repr(C)] struct Vertex { position: [f32; 3], color: [f32; 4] }";
+        let mismatches = check_rust_struct(source, "Vertex", &glsl, &TypeMap::new()).unwrap();
+        let expected = [
+            LayoutMismatch::Offset {
+                field: "color".into(),
+                rust: 12,
+                glsl: 16,
+            },
+            LayoutMismatch::Size {
+                field: "color".into(),
+                rust: 16,
+                glsl: 12,
+            },
+            LayoutMismatch::StructSize { rust: 28, glsl: 32 },
+        ];
+        assert_eq!(mismatches, expected);
+    }
+
+    #[test]
+    fn test_field_covering_padding() {
+        let map = TypeMap::glam().insert(AbstractType::Vec3, "glam::Vec3A", 16, 16);
+        let source = "#[repr(C)] struct Light { direction: glam::Vec3A, color: [f32; 4] }";
+        let fields = [
+            AbstractField::new("direction", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec4),
+        ];
+        let glsl = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        assert_eq!(check_rust_struct(source, "Light", &glsl, &map).unwrap(), []);
+
+        // A float after the vec3 leaves no padding to cover
+        let source = "#[repr(C)] struct Light { direction: glam::Vec3A, color: f32 }";
+        let fields = [
+            AbstractField::new("direction", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Float),
+        ];
+        let glsl = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        let mismatches = check_rust_struct(source, "Light", &glsl, &map).unwrap();
+        assert_eq!(
+            mismatches[0],
+            LayoutMismatch::Size {
+                field: "direction".into(),
+                rust: 16,
+                glsl: 12,
+            }
+        );
+    }
+}
@@ -1,22 +1,142 @@
 mod abstract_data;
+mod c_layout;
+mod diagnostics;
 mod extraction;
 mod glsl_layout;
+mod joint_layout;
+mod layout_check;
+mod optimize;
+mod preprocess;
+mod rust_codegen;
+mod type_map;
 pub use glsl_layout::*;
+pub use c_layout::*;
+pub use optimize::*;
+pub use preprocess::*;
+pub use joint_layout::*;
+pub use layout_check::*;
+pub use rust_codegen::*;
+pub use type_map::*;
 pub use extraction::*;
 pub use abstract_data::*;
-use glsl::syntax::TypeSpecifierNonArray;
+pub use diagnostics::*;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Unsupported data type {:?}", ty)]
+    #[error("Unsupported data type `{}`", ty)]
     UnsupportedType {
-        ty: TypeSpecifierNonArray,
+        ty: String,
     },
-    #[error("Currently, we do not support qualifiers")]
+    #[error("Currently, the only qualifiers we support are row_major, column_major, offset and align")]
     QualifiersUnsupported,
-    #[error("Currently, we do not support arrays")]
+    #[error("Currently, we only support arrays with a constant integer size")]
     ArraysUnsupported,
+    #[error("Struct {} is used before it is defined", name)]
+    UnknownStruct {
+        name: String,
+    },
+    #[error("Runtime-sized array {} must be the last member of a buffer block", name)]
+    MisplacedRuntimeArray {
+        name: String,
+    },
+    #[error("Field {} cannot be placed at the same offset in Rust and GLSL", name)]
+    JointLayoutMismatch {
+        name: String,
+    },
+    #[error("The Rust struct would be {} bytes, but the GLSL struct is {} bytes", rust, glsl)]
+    JointSizeMismatch {
+        rust: u64,
+        glsl: u64,
+    },
+    #[error("No field named {}", name)]
+    UnknownField {
+        name: String,
+    },
+    #[error("Invalid layout constraint: {}", reason)]
+    InvalidConstraint {
+        reason: String,
+    },
+    #[error("No ordering of the fields satisfies every layout constraint")]
+    UnsatisfiableConstraints,
+    #[error("Struct {} would contain implicit padding, which bytemuck::Pod forbids", name)]
+    ImplicitPadding {
+        name: String,
+    },
+    #[error("Struct {} ends in a runtime-sized array, so it can't be bytemuck::Pod", name)]
+    UnsizedPod {
+        name: String,
+    },
+    #[error("Field {} can't be mirrored by {}, which has a different size", name, path)]
+    MappedTypeMismatch {
+        name: String,
+        path: String,
+    },
+    #[error("Failed to parse shader: {}", message)]
+    Parse {
+        message: String,
+    },
+    #[error("No struct or interface block named {}", name)]
+    DefinitionNotFound {
+        name: String,
+    },
+    #[error("{}:{}: {}", file, line, message)]
+    Preprocess {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    #[error("Failed to read file: {}", .0)]
+    Io(#[from] std::io::Error),
+    #[error("Block {} uses the {} layout, whose offsets are implementation defined", name, layout)]
+    UnsupportedBlockLayout {
+        name: String,
+        layout: String,
+    },
+    #[error("Field {} has align = {}, which isn't a power of two", name, align)]
+    InvalidAlign {
+        name: String,
+        align: u64,
+    },
+    #[error("Field {} has offset = {}, which isn't a multiple of its {} byte alignment", name, offset, align)]
+    MisalignedOffset {
+        name: String,
+        offset: u64,
+        align: u64,
+    },
+    #[error("Field {} has offset = {}, which overlaps the previous member ending at {}", name, offset, end)]
+    OverlappingOffset {
+        name: String,
+        offset: u64,
+        end: u64,
+    },
+    #[error("Field {} has an explicit offset or alignment, which only block members may have", name)]
+    ExplicitLayoutOutsideBlock {
+        name: String,
+    },
+    #[error("Anonymous structs have no name to give their Rust counterpart")]
+    AnonymousStruct,
+    #[error("Failed to parse Rust struct: {}", message)]
+    RustParse {
+        message: String,
+    },
+    #[error("Field {} has type {}, whose layout is unknown (is it missing from the type map?)", name, ty)]
+    UnknownRustType {
+        name: String,
+        ty: String,
+    },
+    #[error("Struct {} isn't #[repr(C)], so its layout is up to rustc", name)]
+    NotReprC {
+        name: String,
+    },
+    #[error("In {} {}: {}", kind, name, source)]
+    InDeclaration {
+        kind: DeclarationKind,
+        name: String,
+        source: Box<Error>,
+    },
+    #[error("{}", .0)]
+    Located(Box<Diagnostic>),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
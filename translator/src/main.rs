@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use struct_translator::*;
+
+const USAGE: &str = "Usage:
+    struct_translator translate <shader> --struct <name> [options]
+    struct_translator check <shader> --struct <name> --rust <file> [options]
+
+translate generates a #[repr(C)] Rust struct matching the layout of a GLSL struct or interface block
+check verifies that an existing #[repr(C)] Rust struct matches it, field by field
+
+Options:
+    --struct <name>       Struct or block to translate, by its name or instance name
+    --layout <rule>       std140, std430 or scalar (defaults to the block's own layout)
+    --out <file>          Write the generated code to a file instead of stdout
+    --rust <file>         Rust source holding the struct to check
+    --rust-struct <name>  Name of the Rust struct, if it differs from the GLSL one
+    --type-map <library>  Use glam, nalgebra, mint or cgmath types in place of arrays
+    --bytemuck            Derive bytemuck::Pod and bytemuck::Zeroable
+    --no-assertions       Leave out the compile-time size and offset checks
+    -I <dir>              Add a directory to search for #include files
+    -D <name>[=<value>]   Define a macro before preprocessing
+    -h, --help            Print this message
+
+Exit codes: 0 on success, 1 if the shader can't be translated or the layouts differ,
+2 for invalid arguments
+";
+
+#[derive(Debug)]
+enum Command {
+    Translate(Args),
+    /// Checks the Rust struct in the given file
+    Check(Args, PathBuf),
+    Help,
+}
+
+#[derive(Debug)]
+struct Args {
+    shader: PathBuf,
+    name: String,
+    /// Overrides the layout rule of the definition
+    rule: Option<LayoutRule>,
+    out: Option<PathBuf>,
+    rust: Option<PathBuf>,
+    rust_name: Option<String>,
+    preprocess: PreprocessOptions,
+    /// Everything but the rule, which isn't known until the shader is parsed
+    codegen: CodegenOptions,
+}
+
+fn main() {
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    match command {
+        Command::Help => print!("{}", USAGE),
+        Command::Translate(args) => {
+            let code = translate(&args).unwrap_or_else(|e| fail(&e));
+            match &args.out {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, code) {
+                        eprintln!("error: Failed to write {}: {}", path.display(), e);
+                        exit(1);
+                    }
+                }
+                None => print!("{}", code),
+            }
+        }
+        Command::Check(args, rust) => {
+            let mismatches = check(&args, &rust).unwrap_or_else(|e| fail(&e));
+            let rust_name = args.rust_name.as_ref().unwrap_or(&args.name);
+            if mismatches.is_empty() {
+                println!("{} matches {}", rust_name, args.name);
+            } else {
+                eprintln!("error: {} doesn't match the layout of {}", rust_name, args.name);
+                for mismatch in mismatches {
+                    eprintln!("  - {}", mismatch);
+                }
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Prints an error and exits, leaving located errors to render their own heading
+fn fail(error: &Error) -> ! {
+    match error {
+        Error::Located(_) => eprintln!("{}", error),
+        _ => eprintln!("error: {}", error),
+    }
+    exit(1);
+}
+
+/// Parses a shader and generates the Rust mirror of one of its definitions
+fn translate(args: &Args) -> Result<String> {
+    let (name, glsl, rule) = glsl_layout(args)?;
+    let options = CodegenOptions {
+        rule,
+        ..args.codegen.clone()
+    };
+
+    let rust = mirror_layout_c_mapped(&glsl, &options.type_map)?;
+    let code = generate_rust(&name, &rust, &options)?;
+    Ok(format!(
+        "// Generated from {} by struct_translator\n\n{}",
+        args.shader.display(),
+        code
+    ))
+}
+
+/// Compares the Rust struct in `rust` with the GLSL layout it should mirror
+fn check(args: &Args, rust: &Path) -> Result<Vec<LayoutMismatch>> {
+    let (name, glsl, _) = glsl_layout(args)?;
+    let source = std::fs::read_to_string(rust)?;
+    let rust_name = args.rust_name.as_ref().unwrap_or(&name);
+    check_rust_struct(&source, rust_name, &glsl, &args.codegen.type_map)
+}
+
+/// Parses the shader and lays out the selected definition, returning its name, layout and rule
+fn glsl_layout(args: &Args) -> Result<(String, Vec<FieldGap>, LayoutRule)> {
+    let definition = parse_shader_file_definition_with(&args.shader, &args.name, &args.preprocess)?;
+    let rule = args.rule.unwrap_or_else(|| definition.rule());
+    let glsl = naive_layout_glsl_only(&definition.fields, rule);
+    Ok((definition.name.clone(), glsl, rule))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Command, String> {
+    match args.next().as_deref() {
+        Some("translate") => parse_options(args).map(Command::Translate),
+        Some("check") => {
+            let mut args = parse_options(args)?;
+            let rust = args.rust.take().ok_or("Missing --rust")?;
+            Ok(Command::Check(args, rust))
+        }
+        Some("-h") | Some("--help") => Ok(Command::Help),
+        Some(command) => Err(format!("Unknown command {}", command)),
+        None => Err("Missing command".into()),
+    }
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Args, String> {
+    let mut shader = None;
+    let mut name = None;
+    let mut rule = None;
+    let mut out = None;
+    let mut rust = None;
+    let mut rust_name = None;
+    let mut preprocess = PreprocessOptions::new();
+    let mut codegen = CodegenOptions::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--struct" => name = Some(value()?),
+            "--layout" => rule = Some(parse_rule(&value()?)?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--rust" => rust = Some(PathBuf::from(value()?)),
+            "--rust-struct" => rust_name = Some(value()?),
+            "--type-map" => codegen.type_map = parse_type_map(&value()?)?,
+            "--bytemuck" => codegen.bytemuck = true,
+            "--no-assertions" => codegen.assertions = false,
+            "-I" => preprocess = preprocess.include_path(value()?),
+            "-D" => {
+                let define = value()?;
+                preprocess = match define.split_once('=') {
+                    Some((name, value)) => preprocess.define(name, value),
+                    None => preprocess.define(&define, "1"),
+                };
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if shader.is_none() => shader = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(Args {
+        shader: shader.ok_or("Missing shader path")?,
+        name: name.ok_or("Missing --struct")?,
+        rule,
+        out,
+        rust,
+        rust_name,
+        preprocess,
+        codegen,
+    })
+}
+
+fn parse_rule(rule: &str) -> std::result::Result<LayoutRule, String> {
+    match rule {
+        "std140" => Ok(LayoutRule::Std140),
+        "std430" => Ok(LayoutRule::Std430),
+        "scalar" => Ok(LayoutRule::Scalar),
+        _ => Err(format!("Unknown layout {}, expected std140, std430 or scalar", rule)),
+    }
+}
+
+fn parse_type_map(library: &str) -> std::result::Result<TypeMap, String> {
+    match library {
+        "glam" => Ok(TypeMap::glam()),
+        "nalgebra" => Ok(TypeMap::nalgebra()),
+        "mint" => Ok(TypeMap::mint()),
+        "cgmath" => Ok(TypeMap::cgmath()),
+        _ => Err(format!(
+            "Unknown type map {}, expected glam, nalgebra, mint or cgmath",
+            library
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> std::result::Result<Command, String> {
+        parse_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_translate() {
+        let line = "translate shader.comp --struct Particle --layout std430 --out particle.rs";
+        let args = match args(line).unwrap() {
+            Command::Translate(args) => args,
+            command => panic!("Expected translate, got {:?}", command),
+        };
+        assert_eq!(args.shader, PathBuf::from("shader.comp"));
+        assert_eq!(args.name, "Particle");
+        assert_eq!(args.rule, Some(LayoutRule::Std430));
+        assert_eq!(args.out, Some(PathBuf::from("particle.rs")));
+        assert!(args.codegen.assertions);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(args("").is_err());
+        assert!(args("translate shader.comp").is_err());
+        assert!(args("translate shader.comp --struct").is_err());
+        assert!(args("translate shader.comp --struct A --layout packed").is_err());
+        assert!(args("translate a.comp b.comp --struct A").is_err());
+        assert!(args("check shader.comp --struct A").is_err());
+        assert!(matches!(args("--help"), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn test_translate_example() {
+        let shader = concat!(env!("CARGO_MANIFEST_DIR"), "/../shader_examples/particle_base.comp");
+        let line = format!("translate {} --struct Vertex --no-assertions", shader);
+        let args = match args(&line).unwrap() {
+            Command::Translate(args) => args,
+            command => panic!("Expected translate, got {:?}", command),
+        };
+        let code = translate(&args).unwrap();
+        assert!(code.contains("pub struct Vertex {"));
+        assert!(!code.contains("assert!"));
+    }
+
+    #[test]
+    fn test_check_translated() {
+        let shader = concat!(env!("CARGO_MANIFEST_DIR"), "/../shader_examples/particle_base.comp");
+        // Concurrent test runs each get their own file
+        let file = format!("struct_translator_check_vertex_{}.rs", std::process::id());
+        let rust = std::env::temp_dir().join(file);
+        let line = format!("translate {} --struct Vertex", shader);
+        let code = match args(&line).unwrap() {
+            Command::Translate(args) => translate(&args).unwrap(),
+            command => panic!("Expected translate, got {:?}", command),
+        };
+        std::fs::write(&rust, code).unwrap();
+
+        let line = format!("check {} --struct Vertex --rust {}", shader, rust.display());
+        let (args, rust) = match args(&line).unwrap() {
+            Command::Check(args, rust) => (args, rust),
+            command => panic!("Expected check, got {:?}", command),
+        };
+        let mismatches = check(&args, &rust).unwrap();
+        let _ = std::fs::remove_file(&rust);
+        assert_eq!(mismatches, []);
+    }
+}
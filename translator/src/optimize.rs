@@ -0,0 +1,817 @@
+use crate::abstract_data::*;
+use crate::glsl_layout::*;
+use crate::type_map::TypeMap;
+use crate::Result;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Structs with at most this many fields are reordered by exhaustive search, larger ones heuristically
+/// (fields kept adjacent by a constraint only count once)
+pub const EXACT_SEARCH_LIMIT: usize = 8;
+
+/// Restrictions on how the optimizer may reorder fields
+#[derive(Clone, Debug, Default)]
+pub struct LayoutConstraints {
+    pub pins: Vec<Pin>,
+    /// Groups of fields which must stay adjacent, in the order given
+    pub groups: Vec<Vec<String>>,
+}
+
+/// Fixes a field in place, by name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pin {
+    pub field: String,
+    pub target: PinTarget,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinTarget {
+    /// Position of the field within the reordered struct, counting from 0
+    Index(usize),
+    /// Byte offset of the field within the GLSL layout
+    Offset(u64),
+}
+
+impl LayoutConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps a field at a position, e.g. a header word which must stay first
+    pub fn pin_index(mut self, field: &str, index: usize) -> Self {
+        self.pins.push(Pin {
+            field: field.into(),
+            target: PinTarget::Index(index),
+        });
+        self
+    }
+
+    /// Keeps a field at a byte offset
+    pub fn pin_offset(mut self, field: &str, offset: u64) -> Self {
+        self.pins.push(Pin {
+            field: field.into(),
+            target: PinTarget::Offset(offset),
+        });
+        self
+    }
+
+    /// Keeps fields next to each other, in the order given
+    pub fn adjacent(mut self, fields: &[&str]) -> Self {
+        self.groups.push(fields.iter().map(|&f| f.into()).collect());
+        self
+    }
+}
+
+/// Reorders fields to minimize the padding glsl inserts between them, then lays them out
+/// Fewer padding bytes come first, then fewer separate gaps (each of which needs a padding field in Rust)
+/// Among equally good orderings, the one closest to the declared order is chosen
+pub fn optimize_layout_glsl(fields: &[AbstractField], rule: LayoutRule) -> Vec<FieldGap> {
+    naive_layout_glsl_only(&optimize_order_glsl(fields, rule), rule)
+}
+
+/// Reorders fields to minimize the padding glsl inserts between them
+/// A trailing runtime-sized array always stays last
+pub fn optimize_order_glsl(fields: &[AbstractField], rule: LayoutRule) -> Vec<AbstractField> {
+    optimize_order_glsl_constrained(fields, rule, &LayoutConstraints::default())
+        .expect("Unconstrained fields can always be ordered")
+}
+
+/// Like `optimize_layout_glsl`, but only considers orderings satisfying the constraints
+pub fn optimize_layout_glsl_constrained(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+) -> Result<Vec<FieldGap>> {
+    let order = optimize_order_glsl_constrained(fields, rule, constraints)?;
+    Ok(naive_layout_glsl_only(&order, rule))
+}
+
+/// Like `optimize_order_glsl`, but only considers orderings satisfying the constraints
+pub fn optimize_order_glsl_constrained(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+) -> Result<Vec<AbstractField>> {
+    optimize_order_glsl_mapped(fields, rule, constraints, &TypeMap::default())
+}
+
+/// Like `optimize_layout_glsl_constrained`, but only considers orderings which a `#[repr(C)]` struct
+/// using the type map's types can mirror
+pub fn optimize_layout_glsl_mapped(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+    map: &TypeMap,
+) -> Result<Vec<FieldGap>> {
+    let order = optimize_order_glsl_mapped(fields, rule, constraints, map)?;
+    Ok(naive_layout_glsl_only(&order, rule))
+}
+
+/// Like `optimize_order_glsl_constrained`, but only considers orderings which a `#[repr(C)]` struct
+/// using the type map's types can mirror, e.g. a `glam::Vec3A` can't share its last 4 bytes with a float
+pub fn optimize_order_glsl_mapped(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+    map: &TypeMap,
+) -> Result<Vec<AbstractField>> {
+    let problem = Problem::new(fields, rule, constraints, map)?;
+    let order = if problem.units.len() <= EXACT_SEARCH_LIMIT {
+        problem.exact()
+    } else {
+        problem.heuristic()
+    };
+
+    match order {
+        Some(order) => Ok(order.into_iter().map(|idx| fields[idx].clone()).collect()),
+        None => Err(crate::Error::UnsatisfiableConstraints),
+    }
+}
+
+/// Padding bytes and number of separate gaps, compared in that order
+type Cost = (u64, u64);
+
+/// Adds a gap of `gap` bytes (if any) to a cost
+fn add_gap((bytes, count): Cost, gap: u64) -> Cost {
+    if gap > 0 {
+        (bytes + gap, count + 1)
+    } else {
+        (bytes, count)
+    }
+}
+
+/// Total number of padding bytes in a layout
+pub fn gap_bytes(fgs: &[FieldGap]) -> u64 {
+    fgs.iter()
+        .filter_map(|fg| match fg {
+            FieldGap::Gap(g, _) => Some(g),
+            FieldGap::Field(..) => None,
+        })
+        .sum()
+}
+
+/// Why the optimizer moved a field away from its declared position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveReason {
+    /// A constraint put the field there
+    Pinned(PinTarget),
+    /// The field fills padding which followed another field in the declared order
+    FillsGap { after: String, bytes: u64 },
+    /// The field went ahead of less aligned fields, which then pack in behind it
+    AlignedFirst { align: u64 },
+    /// Other fields moving shifted this one along
+    Displaced,
+}
+
+/// A field the optimizer moved, with positions counted from 0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldMove {
+    pub field: String,
+    pub from: usize,
+    pub to: usize,
+    pub reason: MoveReason,
+}
+
+impl fmt::Display for MoveReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pinned(PinTarget::Index(index)) => write!(f, "pinned to index {}", index),
+            Self::Pinned(PinTarget::Offset(offset)) => write!(f, "pinned to offset {}", offset),
+            Self::FillsGap { after, bytes } => {
+                write!(f, "fills the {}-byte gap after `{}`", bytes, after)
+            }
+            Self::AlignedFirst { align } => write!(
+                f,
+                "placed ahead of less aligned fields due to its {}-byte alignment",
+                align
+            ),
+            Self::Displaced => f.write_str("shifted by the fields moved ahead of it"),
+        }
+    }
+}
+
+impl fmt::Display for FieldMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` moved from {} to {}: {}",
+            self.field, self.from, self.to, self.reason
+        )
+    }
+}
+
+/// Explains each field whose position differs between the declared and optimized orders
+pub fn explain_order_glsl(
+    declared: &[AbstractField],
+    optimized: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+) -> Vec<FieldMove> {
+    let position = |name: &str| declared.iter().position(|f| f.name == name);
+
+    // Padding directly following each field when laid out as declared
+    let mut gap_after = HashMap::new();
+    let layout = naive_layout_glsl_only(declared, rule);
+    for pair in layout.windows(2) {
+        if let [FieldGap::Field(f, _), FieldGap::Gap(g, _)] = pair {
+            gap_after.insert(f.name.as_str(), *g);
+        }
+    }
+
+    let mut moves = Vec::new();
+    for (to, field) in optimized.iter().enumerate() {
+        let from = match position(&field.name) {
+            Some(from) if from != to => from,
+            _ => continue,
+        };
+
+        let pin = constraints.pins.iter().find(|pin| pin.field == field.name);
+        let prev = to.checked_sub(1).map(|prev| &optimized[prev]);
+        let filled = prev.and_then(|prev| {
+            let bytes = *gap_after.get(prev.name.as_str())?;
+            // Following the same field as before doesn't fill anything new
+            match position(&prev.name) {
+                Some(prev_from) if prev_from + 1 != from => Some((prev, bytes)),
+                _ => None,
+            }
+        });
+        let align = field.align_gl(rule);
+        let overtook_less_aligned = optimized[to + 1..].iter().any(|other| {
+            position(&other.name).is_some_and(|other_from| other_from < from)
+                && other.align_gl(rule) < align
+        });
+
+        let reason = if let Some(pin) = pin {
+            MoveReason::Pinned(pin.target)
+        } else if let Some((prev, bytes)) = filled {
+            MoveReason::FillsGap {
+                after: prev.name.clone(),
+                bytes,
+            }
+        } else if overtook_less_aligned {
+            MoveReason::AlignedFirst { align }
+        } else {
+            MoveReason::Displaced
+        };
+
+        moves.push(FieldMove {
+            field: field.name.clone(),
+            from,
+            to,
+            reason,
+        });
+    }
+    moves
+}
+
+/// Describes the declared and optimized layouts side by side with every move explained, for printing
+pub fn optimization_report(
+    fields: &[AbstractField],
+    rule: LayoutRule,
+    constraints: &LayoutConstraints,
+) -> Result<String> {
+    let declared = naive_layout_glsl_only(fields, rule);
+    let order = optimize_order_glsl_constrained(fields, rule, constraints)?;
+    let optimized = naive_layout_glsl_only(&order, rule);
+    let moves = explain_order_glsl(fields, &order, rule, constraints);
+
+    let mut report = format!(
+        "Declared order under {} ({} bytes, {} of them padding):\n{}",
+        rule,
+        layout_size(&declared),
+        gap_bytes(&declared),
+        layout_report(&declared)
+    );
+    if moves.is_empty() {
+        report.push_str("\nThe declared order is already optimal\n");
+        return Ok(report);
+    }
+
+    report.push_str("\nMoves:\n");
+    for m in &moves {
+        report.push_str(&format!("  {}\n", m));
+    }
+    report.push_str(&format!(
+        "\nOptimized order ({} bytes, {} of them padding):\n{}",
+        layout_size(&optimized),
+        gap_bytes(&optimized),
+        layout_report(&optimized)
+    ));
+    Ok(report)
+}
+
+/// Field alignments, sizes and constraints, computed once up front since every ordering needs them
+struct Problem<'a> {
+    fields: &'a [AbstractField],
+    aligns: Vec<u64>,
+    sizes: Vec<u64>,
+    /// What the end of the struct is aligned to, unless it ends in a runtime array
+    struct_align: u64,
+    /// Sizes and alignments of the Rust mirror of each field, where a mapped type may cover the
+    /// padding after it or be more strictly aligned than in GLSL
+    rust_sizes: Vec<u64>,
+    rust_aligns: Vec<u64>,
+    rust_struct_align: u64,
+    /// Fields which are always placed together, in declared order of their first field
+    units: Vec<Vec<usize>>,
+    index_pins: Vec<Option<usize>>,
+    offset_pins: Vec<Option<u64>>,
+}
+
+/// The result of placing fields starting from some offset and position
+#[derive(Copy, Clone)]
+struct Placement {
+    offset: u64,
+    position: usize,
+    cost: Cost,
+    /// Where the Rust mirror of the last placed field ends
+    rust_end: u64,
+}
+
+impl<'a> Problem<'a> {
+    fn new(
+        fields: &'a [AbstractField],
+        rule: LayoutRule,
+        constraints: &LayoutConstraints,
+        map: &TypeMap,
+    ) -> Result<Self> {
+        let lookup: HashMap<&str, usize> = fields
+            .iter()
+            .enumerate()
+            .map(|(idx, f)| (f.name.as_str(), idx))
+            .collect();
+        let find = |name: &str| {
+            lookup
+                .get(name)
+                .copied()
+                .ok_or_else(|| crate::Error::UnknownField { name: name.into() })
+        };
+        let invalid = |reason: String| Err(crate::Error::InvalidConstraint { reason });
+
+        let aligns: Vec<u64> = fields.iter().map(|f| f.align_gl(rule)).collect();
+        let mut index_pins = vec![None; fields.len()];
+        let mut offset_pins = vec![None; fields.len()];
+
+        // A runtime-sized array always has to come last
+        let runtime_pins = fields
+            .iter()
+            .filter(|f| f.ty.is_runtime_array())
+            .map(|f| Pin {
+                field: f.name.clone(),
+                target: PinTarget::Index(fields.len() - 1),
+            });
+
+        for pin in constraints.pins.iter().cloned().chain(runtime_pins) {
+            let idx = find(&pin.field)?;
+            match pin.target {
+                PinTarget::Index(index) => {
+                    if index >= fields.len() {
+                        return invalid(format!(
+                            "{} is pinned to index {}, but there are only {} fields",
+                            pin.field,
+                            index,
+                            fields.len()
+                        ));
+                    }
+                    if let Some(other) = index_pins.iter().position(|&p| p == Some(index)) {
+                        if other != idx {
+                            return invalid(format!(
+                                "{} and {} are both pinned to index {}",
+                                fields[other].name, pin.field, index
+                            ));
+                        }
+                    }
+                    if index_pins[idx].is_some_and(|p| p != index) {
+                        return invalid(format!("{} is pinned to two indices", pin.field));
+                    }
+                    index_pins[idx] = Some(index);
+                }
+                PinTarget::Offset(offset) => {
+                    if offset % aligns[idx] != 0 {
+                        return invalid(format!(
+                            "{} is pinned to offset {}, which breaks its {} byte alignment",
+                            pin.field, offset, aligns[idx]
+                        ));
+                    }
+                    if offset_pins[idx].is_some_and(|p| p != offset) {
+                        return invalid(format!("{} is pinned to two offsets", pin.field));
+                    }
+                    offset_pins[idx] = Some(offset);
+                }
+            }
+        }
+
+        // Every field belongs to exactly one unit, ordered by where its first field was declared
+        let mut unit_of: Vec<Option<usize>> = vec![None; fields.len()];
+        let mut units: Vec<Vec<usize>> = Vec::new();
+        for group in &constraints.groups {
+            if group.is_empty() {
+                return invalid("An adjacent group must name at least one field".into());
+            }
+            let mut unit = Vec::with_capacity(group.len());
+            for name in group {
+                let idx = find(name)?;
+                if unit_of[idx].is_some() || unit.contains(&idx) {
+                    return invalid(format!("{} appears in more than one adjacent group", name));
+                }
+                unit.push(idx);
+            }
+            for &idx in &unit {
+                unit_of[idx] = Some(units.len());
+            }
+            units.push(unit);
+        }
+        for (idx, unit) in unit_of.iter().enumerate() {
+            if unit.is_none() {
+                units.push(vec![idx]);
+            }
+        }
+        units.sort_by_key(|unit| unit.iter().min().copied());
+
+        let sizes: Vec<u64> = fields.iter().map(|f| f.ty.size_gl(rule)).collect();
+        let rust_sizes = fields.iter().map(|f| map.size_c(&f.ty, rule)).collect();
+        let rust_aligns: Vec<u64> = fields.iter().map(|f| map.align_c(&f.ty)).collect();
+
+        Ok(Self {
+            fields,
+            sizes,
+            struct_align: struct_align_gl(fields, rule),
+            rust_struct_align: rust_aligns.iter().copied().max().unwrap_or(1),
+            rust_sizes,
+            rust_aligns,
+            aligns,
+            units,
+            index_pins,
+            offset_pins,
+        })
+    }
+
+    /// Places the fields of a unit, or returns None if that would break a pin
+    fn place(&self, unit: &[usize], mut placement: Placement) -> Option<Placement> {
+        for &idx in unit {
+            let natural = round_up(placement.offset, self.aligns[idx]);
+            let offset = match self.fields[idx].offset {
+                // Fields with a layout(offset = N) qualifier get padded up to it
+                Some(explicit) if explicit >= natural => round_up(explicit, self.aligns[idx]),
+                Some(_) => return None,
+                None => natural,
+            };
+            let gap = offset - placement.offset;
+            if self.index_pins[idx].is_some_and(|p| p != placement.position) {
+                return None;
+            }
+            if self.offset_pins[idx].is_some_and(|p| p != offset) {
+                return None;
+            }
+            // The Rust mirror has to land on the same offset, without overlapping the previous field
+            if offset % self.rust_aligns[idx] != 0 || offset < placement.rust_end {
+                return None;
+            }
+            placement = Placement {
+                offset: offset + self.sizes[idx],
+                position: placement.position + 1,
+                cost: add_gap(placement.cost, gap),
+                rust_end: offset + self.rust_sizes[idx],
+            };
+        }
+        Some(placement)
+    }
+
+    /// Whether an unplaced field's pin (or explicit offset) has already been passed by
+    fn missed_pin(&self, placed: &[bool], placement: Placement) -> bool {
+        (0..self.fields.len()).any(|idx| {
+            !placed[idx]
+                && (self.index_pins[idx].is_some_and(|p| p < placement.position)
+                    || self.offset_pins[idx].is_some_and(|p| p < placement.offset)
+                    || self.fields[idx].offset.is_some_and(|p| p < placement.offset))
+        })
+    }
+
+    /// Adds the padding at the end of the struct, once every field has been placed
+    /// Returns None if the Rust mirror would end up a different size
+    fn finish(&self, order: &[usize], placement: Placement) -> Option<Cost> {
+        match order.last() {
+            Some(&idx) if self.fields[idx].ty.is_runtime_array() => Some(placement.cost),
+            _ => {
+                let size = round_up(placement.offset, self.struct_align);
+                if size < placement.rust_end || !size.is_multiple_of(self.rust_struct_align) {
+                    return None;
+                }
+                Some(add_gap(placement.cost, size - placement.offset))
+            }
+        }
+    }
+
+    /// Cost of a complete ordering of units, or None if it breaks a constraint
+    fn cost(&self, units: &[usize]) -> Option<(Cost, Vec<usize>)> {
+        let mut placement = START;
+        let mut order = Vec::with_capacity(self.fields.len());
+        for &unit in units {
+            placement = self.place(&self.units[unit], placement)?;
+            order.extend_from_slice(&self.units[unit]);
+        }
+        Some((self.finish(&order, placement)?, order))
+    }
+
+    /// Branch and bound over every ordering, visited in lexicographic order starting from the declared one
+    fn exact(&self) -> Option<Vec<usize>> {
+        let mut search = ExactSearch {
+            problem: self,
+            order: Vec::with_capacity(self.fields.len()),
+            used: vec![false; self.units.len()],
+            placed: vec![false; self.fields.len()],
+            best: None,
+        };
+        search.search(START);
+        search.best.map(|(_, order)| order)
+    }
+
+    /// Greedily places whichever unit wastes the fewest bytes at the current offset,
+    /// preferring larger alignments (which are harder to place later) on ties
+    fn heuristic(&self) -> Option<Vec<usize>> {
+        let mut greedy = Vec::with_capacity(self.units.len());
+        let mut remaining: Vec<usize> = (0..self.units.len()).collect();
+        let mut placement = START;
+        while !remaining.is_empty() {
+            // Units which fit here without breaking a pin
+            let next = remaining
+                .iter()
+                .enumerate()
+                .filter_map(|(pos, &unit)| {
+                    let placed = self.place(&self.units[unit], placement)?;
+                    let first = self.units[unit][0];
+                    let key = (
+                        // A unit pinned right here must go here
+                        self.index_pins[first] != Some(placement.position),
+                        placed.cost,
+                        std::cmp::Reverse(self.aligns[first]),
+                        std::cmp::Reverse(self.sizes[first]),
+                    );
+                    Some((key, pos, placed))
+                })
+                .min_by_key(|&(key, _, _)| key);
+
+            let (_, pos, placed) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            placement = placed;
+            greedy.push(remaining.remove(pos));
+        }
+
+        // The greedy pass can get stuck, or do worse than simply keeping the declared order
+        let declared: Vec<usize> = (0..self.units.len()).collect();
+        let candidates = [greedy, declared];
+        candidates
+            .iter()
+            .filter(|units| units.len() == self.units.len())
+            .filter_map(|units| self.cost(units))
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, order)| order)
+    }
+}
+
+const START: Placement = Placement {
+    offset: 0,
+    position: 0,
+    cost: (0, 0),
+    rust_end: 0,
+};
+
+struct ExactSearch<'p, 'a> {
+    problem: &'p Problem<'a>,
+    order: Vec<usize>,
+    used: Vec<bool>,
+    placed: Vec<bool>,
+    best: Option<(Cost, Vec<usize>)>,
+}
+
+impl ExactSearch<'_, '_> {
+    fn search(&mut self, placement: Placement) {
+        // Costs only ever grow, so there's no beating the best ordering from here
+        if let Some((best, _)) = &self.best {
+            if placement.cost >= *best {
+                return;
+            }
+        }
+
+        let problem = self.problem;
+        if problem.missed_pin(&self.placed, placement) {
+            return;
+        }
+
+        if self.order.len() == problem.fields.len() {
+            let total = match problem.finish(&self.order, placement) {
+                Some(total) => total,
+                None => return,
+            };
+            let improved = match &self.best {
+                Some((best, _)) => total < *best,
+                None => true,
+            };
+            if improved {
+                self.best = Some((total, self.order.clone()));
+            }
+            return;
+        }
+
+        for unit in 0..problem.units.len() {
+            if self.used[unit] || self.interchangeable_with_earlier(unit) {
+                continue;
+            }
+
+            let fields = &problem.units[unit];
+            let next = match problem.place(fields, placement) {
+                Some(next) => next,
+                None => continue,
+            };
+
+            self.used[unit] = true;
+            for &idx in fields {
+                self.placed[idx] = true;
+                self.order.push(idx);
+            }
+            self.search(next);
+            for &idx in fields {
+                self.placed[idx] = false;
+                self.order.pop();
+            }
+            self.used[unit] = false;
+        }
+    }
+
+    /// Swapping two unpinned fields of the same type, explicit offset and alignment can't change
+    /// the layout
+    fn interchangeable_with_earlier(&self, unit: usize) -> bool {
+        let problem = self.problem;
+        let single = |unit: usize| match problem.units[unit].as_slice() {
+            &[idx] if problem.index_pins[idx].is_none() && problem.offset_pins[idx].is_none() => {
+                Some(idx)
+            }
+            _ => None,
+        };
+        let idx = match single(unit) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        (0..unit).any(|prev| {
+            let same_layout = |prev: usize| {
+                let (prev, field) = (&problem.fields[prev], &problem.fields[idx]);
+                prev.ty == field.ty && prev.offset == field.offset && prev.align == field.align
+            };
+            !self.used[prev] && single(prev).is_some_and(same_layout)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(fields: &[AbstractField]) -> Vec<&str> {
+        fields.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_readme_particle() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("velocity", AbstractType::Vec3),
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("charge", AbstractType::Float),
+        ];
+        let order = optimize_order_glsl(&fields, LayoutRule::Std140);
+        assert_eq!(names(&order), ["position", "mass", "velocity", "charge"]);
+
+        let layout = optimize_layout_glsl(&fields, LayoutRule::Std140);
+        assert_eq!(gap_bytes(&layout), 0);
+        assert_eq!(layout_size(&layout), 32);
+    }
+
+    #[test]
+    fn test_explain_readme_particle() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("velocity", AbstractType::Vec3),
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("charge", AbstractType::Float),
+        ];
+        let order = optimize_order_glsl(&fields, LayoutRule::Std140);
+        let constraints = LayoutConstraints::default();
+        let moves = explain_order_glsl(&fields, &order, LayoutRule::Std140, &constraints);
+
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].field, "mass");
+        assert_eq!(
+            moves[0].reason,
+            MoveReason::FillsGap {
+                after: "position".into(),
+                bytes: 4
+            }
+        );
+        assert_eq!(moves[1].field, "velocity");
+        assert_eq!(moves[1].reason, MoveReason::Displaced);
+        assert_eq!(
+            moves[0].to_string(),
+            "`mass` moved from 2 to 1: fills the 4-byte gap after `position`"
+        );
+    }
+
+    #[test]
+    fn test_already_optimal_keeps_order() {
+        let fields = [
+            AbstractField::new("a", AbstractType::Vec4),
+            AbstractField::new("b", AbstractType::Float),
+        ];
+        let order = optimize_order_glsl(&fields, LayoutRule::Std430);
+        assert_eq!(names(&order), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_explicit_offset_not_interchangeable() {
+        let fields = [
+            AbstractField {
+                offset: Some(16),
+                ..AbstractField::new("a", AbstractType::Vec4)
+            },
+            AbstractField::new("b", AbstractType::Vec4),
+        ];
+        let order = optimize_order_glsl(&fields, LayoutRule::Std140);
+        assert_eq!(names(&order), ["b", "a"]);
+        assert_eq!(gap_bytes(&optimize_layout_glsl(&fields, LayoutRule::Std140)), 0);
+    }
+
+    #[test]
+    fn test_constraints() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("velocity", AbstractType::Vec3),
+            AbstractField::new("mass", AbstractType::Float),
+            AbstractField::new("charge", AbstractType::Float),
+            AbstractField::new("flags", AbstractType::UInt),
+        ];
+
+        let constraints = LayoutConstraints::new()
+            .pin_index("flags", 0)
+            .adjacent(&["position", "velocity"]);
+        let order = optimize_order_glsl_constrained(&fields, LayoutRule::Std430, &constraints);
+        let order = order.unwrap();
+        assert_eq!(order[0].name, "flags");
+        let position = order.iter().position(|f| f.name == "position").unwrap();
+        assert_eq!(order[position + 1].name, "velocity");
+
+        let constraints = LayoutConstraints::new().pin_offset("mass", 16);
+        let layout = optimize_layout_glsl_constrained(&fields, LayoutRule::Std430, &constraints);
+        let mut offset = 0;
+        for fg in layout.unwrap() {
+            if let FieldGap::Field(f, _) = &fg {
+                if f.name == "mass" {
+                    assert_eq!(offset, 16);
+                }
+            }
+            offset += fg.size();
+        }
+    }
+
+    #[test]
+    fn test_constraint_errors() {
+        let fields = [
+            AbstractField::new("a", AbstractType::Vec3),
+            AbstractField::new("b", AbstractType::Float),
+        ];
+        let optimize = |constraints: LayoutConstraints| {
+            optimize_order_glsl_constrained(&fields, LayoutRule::Std140, &constraints)
+        };
+
+        let unknown = optimize(LayoutConstraints::new().pin_index("c", 0));
+        assert!(matches!(unknown, Err(crate::Error::UnknownField { .. })));
+
+        let clash = optimize(LayoutConstraints::new().pin_index("a", 0).pin_index("b", 0));
+        assert!(matches!(clash, Err(crate::Error::InvalidConstraint { .. })));
+
+        let empty = optimize(LayoutConstraints::new().adjacent(&[]));
+        assert!(matches!(empty, Err(crate::Error::InvalidConstraint { .. })));
+
+        let misaligned = optimize(LayoutConstraints::new().pin_offset("a", 4));
+        assert!(matches!(misaligned, Err(crate::Error::InvalidConstraint { .. })));
+
+        let impossible = optimize(LayoutConstraints::new().pin_offset("b", 32));
+        assert!(matches!(impossible, Err(crate::Error::UnsatisfiableConstraints)));
+    }
+
+    #[test]
+    fn test_large_struct_heuristic() {
+        let mut fields = Vec::new();
+        for i in 0..EXACT_SEARCH_LIMIT {
+            fields.push(AbstractField::new(format!("v{}", i), AbstractType::Vec3));
+            fields.push(AbstractField::new(format!("f{}", i), AbstractType::Float));
+        }
+        // All the vec3s first, leaving a hole after each one
+        fields.sort_by_key(|f| f.ty != AbstractType::Vec3);
+
+        let declared = naive_layout_glsl_only(&fields, LayoutRule::Std430);
+        let optimized = optimize_layout_glsl(&fields, LayoutRule::Std430);
+        assert!(gap_bytes(&declared) > 0);
+        assert_eq!(gap_bytes(&optimized), 0);
+    }
+}
@@ -0,0 +1,754 @@
+use crate::Result;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+/// Includes nested deeper than this are assumed to be a cycle
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Settings for the preprocessor
+#[derive(Clone, Debug, Default)]
+pub struct PreprocessOptions {
+    /// Directories searched for `#include`d files, after the directory of the including file
+    pub include_paths: Vec<PathBuf>,
+    /// Object-like macros defined before the shader starts, like `-D NAME=VALUE` on the command line
+    pub defines: Vec<(String, String)>,
+}
+
+impl PreprocessOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Where a line of preprocessed output came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The file the line was read from, or None for source passed in directly
+    pub file: Option<PathBuf>,
+    /// Line number within that file, counting from 1
+    pub line: usize,
+    /// The line as written, before macros are expanded (with any continuation lines joined on)
+    pub text: String,
+}
+
+/// Shader source with every directive but `#version`, `#extension` and `#pragma` resolved
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    pub source: String,
+    /// The origin of each line of `source`
+    pub lines: Vec<SourceLine>,
+}
+
+impl Preprocessed {
+    /// The origin of a line of the output, counting from 1
+    pub fn origin(&self, line: usize) -> Option<&SourceLine> {
+        line.checked_sub(1).and_then(|idx| self.lines.get(idx))
+    }
+}
+
+/// Preprocesses shader source, resolving includes against the search paths only
+pub fn preprocess(source: &str, options: &PreprocessOptions) -> Result<Preprocessed> {
+    let mut preprocessor = Preprocessor::new(options);
+    preprocessor.process(source, None, 0)?;
+    Ok(preprocessor.finish())
+}
+
+/// Reads and preprocesses a shader file, resolving includes relative to it first
+pub fn preprocess_file<P: AsRef<Path>>(
+    path: P,
+    options: &PreprocessOptions,
+) -> Result<Preprocessed> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    let mut preprocessor = Preprocessor::new(options);
+    preprocessor.process(&source, Some(path), 0)?;
+    Ok(preprocessor.finish())
+}
+
+/// One level of `#if`/`#ifdef`/`#ifndef` nesting
+struct Conditional {
+    /// Whether lines in the current branch are kept
+    active: bool,
+    /// Whether any branch so far was taken
+    taken: bool,
+    /// Whether the enclosing region is kept at all
+    parent_active: bool,
+    seen_else: bool,
+}
+
+struct Preprocessor<'a> {
+    options: &'a PreprocessOptions,
+    macros: HashMap<String, String>,
+    /// Function-like macros, which are left unexpanded as their arguments aren't substituted
+    function_macros: HashSet<String>,
+    /// Files which asked to be included only once with `#pragma once`
+    once: HashSet<PathBuf>,
+    output: String,
+    lines: Vec<SourceLine>,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn new(options: &'a PreprocessOptions) -> Self {
+        Self {
+            options,
+            macros: options.defines.iter().cloned().collect(),
+            function_macros: HashSet::new(),
+            once: HashSet::new(),
+            output: String::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Preprocessed {
+        Preprocessed {
+            source: self.output,
+            lines: self.lines,
+        }
+    }
+
+    fn emit(&mut self, text: &str, origin: &SourceLine) {
+        self.output.push_str(text);
+        self.output.push('\n');
+        self.lines.push(origin.clone());
+    }
+
+    fn process(&mut self, source: &str, file: Option<&Path>, depth: usize) -> Result<()> {
+        let mut stack: Vec<Conditional> = Vec::new();
+        let mut in_comment = false;
+
+        for (line, text) in join_continuations(source) {
+            let origin = SourceLine {
+                file: file.map(Path::to_path_buf),
+                line,
+                text: text.clone(),
+            };
+            let error = |message: String| preprocess_error(&origin, message);
+            let active = stack.iter().all(|c| c.active);
+
+            let directive = match text.trim_start().strip_prefix('#') {
+                Some(directive) if !in_comment => directive.trim_start(),
+                _ => {
+                    if active {
+                        let expanded = self.expand_line(&text, &mut in_comment);
+                        self.emit(&expanded, &origin);
+                    } else {
+                        // Comments still open and close in skipped regions
+                        scan_comments(&text, &mut in_comment);
+                        self.emit("", &origin);
+                    }
+                    continue;
+                }
+            };
+            let name_len = identifier_len(directive);
+            let (name, rest) = directive.split_at(name_len);
+            let rest = strip_comments(rest);
+            let rest = rest.trim();
+
+            match name {
+                "if" | "ifdef" | "ifndef" => {
+                    // Conditions in skipped regions aren't evaluated, as they may not be valid
+                    let condition = active
+                        && match name {
+                            "if" => self.evaluate(rest).map_err(error)? != 0,
+                            "ifdef" => self.is_defined(macro_name(rest, &error)?),
+                            _ => !self.is_defined(macro_name(rest, &error)?),
+                        };
+                    stack.push(Conditional {
+                        active: condition,
+                        taken: condition,
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                }
+                "elif" => {
+                    let conditional = match stack.last() {
+                        Some(c) if !c.seen_else => c,
+                        Some(_) => return Err(error("#elif after #else".into())),
+                        None => return Err(error("#elif without #if".into())),
+                    };
+                    let condition = conditional.parent_active
+                        && !conditional.taken
+                        && self.evaluate(rest).map_err(error)? != 0;
+                    let conditional = stack.last_mut().unwrap();
+                    conditional.active = condition;
+                    conditional.taken |= condition;
+                }
+                "else" => {
+                    let conditional = match stack.last_mut() {
+                        Some(c) if !c.seen_else => c,
+                        Some(_) => return Err(error("#else after #else".into())),
+                        None => return Err(error("#else without #if".into())),
+                    };
+                    conditional.active = conditional.parent_active && !conditional.taken;
+                    conditional.taken = true;
+                    conditional.seen_else = true;
+                }
+                "endif" => {
+                    if stack.pop().is_none() {
+                        return Err(error("#endif without #if".into()));
+                    }
+                }
+                _ if !active => (),
+                "define" => {
+                    let name = &rest[..identifier_len(rest)];
+                    if name.is_empty() {
+                        return Err(error("#define without a macro name".into()));
+                    }
+                    let value = &rest[name.len()..];
+                    // Only a parenthesis right after the name makes a macro function-like
+                    if value.starts_with('(') {
+                        self.macros.remove(name);
+                        self.function_macros.insert(name.into());
+                    } else {
+                        self.function_macros.remove(name);
+                        self.macros.insert(name.into(), value.trim().into());
+                    }
+                }
+                "undef" => {
+                    let name = macro_name(rest, &error)?;
+                    self.macros.remove(name);
+                    self.function_macros.remove(name);
+                }
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(error("#include nested too deeply, is it a cycle?".into()));
+                    }
+                    let target = include_target(rest).ok_or_else(|| {
+                        error("#include needs a \"file\" or <file> to include".into())
+                    })?;
+                    let path = self.resolve(target, file).ok_or_else(|| {
+                        error(format!("Couldn't find {} to include", target))
+                    })?;
+                    if !self.once.contains(&canonical(&path)) {
+                        let source = std::fs::read_to_string(&path)?;
+                        self.process(&source, Some(&path), depth + 1)?;
+                    }
+                    // The included lines stand in for the directive itself
+                    continue;
+                }
+                "error" => return Err(error(format!("#error {}", rest))),
+                "pragma" if rest == "once" => {
+                    if let Some(file) = file {
+                        self.once.insert(canonical(file));
+                    }
+                }
+                // Only the including file's #version is kept, as it has to come first
+                "version" if depth > 0 => (),
+                "version" | "extension" | "pragma" | "line" => {
+                    self.emit(&text, &origin);
+                    continue;
+                }
+                // A lone # does nothing
+                "" => (),
+                _ => return Err(error(format!("Unknown directive #{}", name))),
+            }
+
+            // Keeps line numbers in the output close to those of the input
+            self.emit("", &origin);
+        }
+
+        if !stack.is_empty() {
+            let origin = SourceLine {
+                file: file.map(Path::to_path_buf),
+                line: source.lines().count(),
+                text: source.lines().last().unwrap_or_default().into(),
+            };
+            return Err(preprocess_error(&origin, "#if without #endif".into()));
+        }
+        Ok(())
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name) || self.function_macros.contains(name)
+    }
+
+    /// Looks for an included file next to the including one, then along the search paths
+    fn resolve(&self, target: &str, from: Option<&Path>) -> Option<PathBuf> {
+        let local = from.and_then(Path::parent).map(|dir| dir.join(target));
+        let searched = self.options.include_paths.iter().map(|dir| dir.join(target));
+        local.into_iter().chain(searched).find(|path| path.is_file())
+    }
+
+    /// Substitutes macros outside of comments
+    fn expand_line(&self, text: &str, in_comment: &mut bool) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            if *in_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        output.push_str(&rest[..end + 2]);
+                        rest = &rest[end + 2..];
+                        *in_comment = false;
+                    }
+                    None => {
+                        output.push_str(rest);
+                        break;
+                    }
+                }
+            } else if rest.starts_with("//") {
+                output.push_str(rest);
+                break;
+            } else if rest.starts_with("/*") {
+                output.push_str("/*");
+                rest = &rest[2..];
+                *in_comment = true;
+            } else {
+                let len = token_len(rest);
+                let token = &rest[..len];
+                match self.macros.get(token) {
+                    Some(_) => output.push_str(&self.expand(token, &mut Vec::new())),
+                    None => output.push_str(token),
+                }
+                rest = &rest[len..];
+            }
+        }
+        output
+    }
+
+    /// Expands every macro in `text`, except those already being expanded (which would recurse forever)
+    fn expand(&self, text: &str, expanding: &mut Vec<String>) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            let len = token_len(rest);
+            let token = &rest[..len];
+            match self.macros.get(token) {
+                Some(value) if !expanding.iter().any(|m| m == token) => {
+                    expanding.push(token.into());
+                    output.push_str(&self.expand(value, expanding));
+                    expanding.pop();
+                }
+                _ => output.push_str(token),
+            }
+            rest = &rest[len..];
+        }
+        output
+    }
+
+    /// Evaluates the condition of an `#if` or `#elif`
+    fn evaluate(&self, condition: &str) -> std::result::Result<i64, String> {
+        // `defined` has to be resolved before macros are expanded, as it looks at their names
+        let mut resolved = String::new();
+        let mut tokens = Tokens::new(condition);
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Ident("defined") => {
+                    let parenthesized = tokens.peek() == Some(Token::Punct("("));
+                    if parenthesized {
+                        tokens.next();
+                    }
+                    let name = match tokens.next() {
+                        Some(Token::Ident(name)) => name,
+                        _ => return Err("defined needs a macro name".into()),
+                    };
+                    if parenthesized && tokens.next() != Some(Token::Punct(")")) {
+                        return Err("Expected ) after defined(".into());
+                    }
+                    resolved.push_str(if self.is_defined(name) { " 1 " } else { " 0 " });
+                }
+                Token::Ident(name) if self.function_macros.contains(name) => {
+                    return Err(format!("Function-like macro {} is unsupported in #if", name));
+                }
+                Token::Ident(name) => {
+                    resolved.push(' ');
+                    resolved.push_str(&self.expand(name, &mut Vec::new()));
+                    resolved.push(' ');
+                }
+                Token::Number(n) | Token::Punct(n) => {
+                    resolved.push(' ');
+                    resolved.push_str(n);
+                    resolved.push(' ');
+                }
+            }
+        }
+
+        let mut parser = ExprParser {
+            tokens: Tokens::new(&resolved),
+        };
+        let value = parser.expression(0)?;
+        match parser.tokens.next() {
+            None => Ok(value),
+            Some(token) => Err(format!("Unexpected {} in #if condition", token.text())),
+        }
+    }
+}
+
+fn preprocess_error(origin: &SourceLine, message: String) -> crate::Error {
+    let file = match &origin.file {
+        Some(file) => file.display().to_string(),
+        None => "<source>".into(),
+    };
+    crate::Error::Preprocess {
+        file,
+        line: origin.line,
+        message,
+    }
+}
+
+/// The single macro name after `#ifdef`, `#ifndef` or `#undef`
+fn macro_name<'t>(rest: &'t str, error: &impl Fn(String) -> crate::Error) -> Result<&'t str> {
+    let len = identifier_len(rest);
+    if len == 0 || !rest[len..].trim().is_empty() {
+        return Err(error(format!("Expected a macro name, found {:?}", rest)));
+    }
+    Ok(&rest[..len])
+}
+
+/// The file named by `"file"` or `<file>`
+fn include_target(rest: &str) -> Option<&str> {
+    let (open, close) = match rest.chars().next()? {
+        '"' => ('"', '"'),
+        '<' => ('<', '>'),
+        _ => return None,
+    };
+    let inner = rest.strip_prefix(open)?;
+    let end = inner.find(close)?;
+    Some(&inner[..end])
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Joins lines ending in a backslash with the next, numbering each joined line by where it starts
+fn join_continuations(source: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (idx, line) in source.lines().enumerate() {
+        let (number, mut text) = pending.take().unwrap_or((idx + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(start) => {
+                text.push_str(start);
+                pending = Some((number, text));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((number, text));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// Removes comments from a directive, which may not span lines
+fn strip_comments(text: &str) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with("//") {
+            break;
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map_or("", |end| &after[end + 2..]);
+            output.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    output
+}
+
+/// Tracks block comments opening and closing in a line which is otherwise ignored
+fn scan_comments(text: &str, in_comment: &mut bool) {
+    let mut rest = text;
+    loop {
+        let next = if *in_comment { "*/" } else { "/*" };
+        if !*in_comment {
+            if let Some(line_comment) = rest.find("//") {
+                match rest.find("/*") {
+                    Some(block) if block < line_comment => (),
+                    _ => return,
+                }
+            }
+        }
+        match rest.find(next) {
+            Some(idx) => {
+                rest = &rest[idx + 2..];
+                *in_comment = !*in_comment;
+            }
+            None => return,
+        }
+    }
+}
+
+fn identifier_len(text: &str) -> usize {
+    let mut chars = text.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return 0,
+    }
+    chars
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(text.len(), |(idx, _)| idx)
+}
+
+/// Length of the identifier, number (like `0x1Fu` or `1.0f`) or other character starting `text`
+fn token_len(text: &str) -> usize {
+    let first = text.chars().next().map_or(0, char::len_utf8);
+    match text.chars().next() {
+        Some(c) if c.is_ascii_digit() => text
+            .char_indices()
+            .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .map_or(text.len(), |(idx, _)| idx),
+        _ => identifier_len(text).max(first),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Token<'t> {
+    Ident(&'t str),
+    Number(&'t str),
+    Punct(&'t str),
+}
+
+impl<'t> Token<'t> {
+    fn text(&self) -> &'t str {
+        match *self {
+            Token::Ident(t) | Token::Number(t) | Token::Punct(t) => t,
+        }
+    }
+}
+
+/// Splits an `#if` condition into tokens
+#[derive(Clone)]
+struct Tokens<'t> {
+    rest: &'t str,
+}
+
+impl<'t> Tokens<'t> {
+    fn new(text: &'t str) -> Self {
+        Self { rest: text }
+    }
+
+    fn peek(&self) -> Option<Token<'t>> {
+        self.clone().next()
+    }
+}
+
+impl<'t> Iterator for Tokens<'t> {
+    type Item = Token<'t>;
+
+    fn next(&mut self) -> Option<Token<'t>> {
+        const PUNCTS: &[&str] = &[
+            "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "!", "~", "*", "/", "%", "+",
+            "-", "<", ">", "&", "^", "|",
+        ];
+        self.rest = self.rest.trim_start();
+        let first = self.rest.chars().next()?;
+        let len = if first.is_ascii_digit() || identifier_len(self.rest) > 0 {
+            token_len(self.rest)
+        } else {
+            PUNCTS
+                .iter()
+                .find(|p| self.rest.starts_with(*p))
+                .map_or(first.len_utf8(), |p| p.len())
+        };
+        let (text, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(if first.is_ascii_digit() {
+            Token::Number(text)
+        } else if identifier_len(text) > 0 {
+            Token::Ident(text)
+        } else {
+            Token::Punct(text)
+        })
+    }
+}
+
+/// Evaluates integer expressions by precedence climbing, as in C's `#if`
+struct ExprParser<'t> {
+    tokens: Tokens<'t>,
+}
+
+type EvalResult = std::result::Result<i64, String>;
+
+impl ExprParser<'_> {
+    /// Binding strength of a binary operator, higher binding tighter
+    fn precedence(op: &str) -> Option<u8> {
+        Some(match op {
+            "||" => 1,
+            "&&" => 2,
+            "|" => 3,
+            "^" => 4,
+            "&" => 5,
+            "==" | "!=" => 6,
+            "<" | ">" | "<=" | ">=" => 7,
+            "<<" | ">>" => 8,
+            "+" | "-" => 9,
+            "*" | "/" | "%" => 10,
+            _ => return None,
+        })
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> EvalResult {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Punct(op)) = self.tokens.peek() {
+            let precedence = match Self::precedence(op) {
+                Some(p) if p > min_precedence => p,
+                _ => break,
+            };
+            self.tokens.next();
+            let rhs = self.expression(precedence)?;
+            lhs = apply(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> EvalResult {
+        match self.tokens.next() {
+            Some(Token::Punct("!")) => Ok((self.unary()? == 0) as i64),
+            Some(Token::Punct("~")) => Ok(!self.unary()?),
+            Some(Token::Punct("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Punct("+")) => self.unary(),
+            Some(Token::Punct("(")) => {
+                let value = self.expression(0)?;
+                match self.tokens.next() {
+                    Some(Token::Punct(")")) => Ok(value),
+                    _ => Err("Expected ) in #if condition".into()),
+                }
+            }
+            Some(Token::Number(n)) => parse_integer(n),
+            // Identifiers left after expanding macros count as 0
+            Some(Token::Ident(_)) => Ok(0),
+            Some(token) => Err(format!("Unexpected {} in #if condition", token.text())),
+            None => Err("Incomplete #if condition".into()),
+        }
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> EvalResult {
+    let shift = || u32::try_from(rhs).map_err(|_| format!("Can't shift by {}", rhs));
+    Ok(match op {
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(shift()?),
+        ">>" => lhs.wrapping_shr(shift()?),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("Division by zero in #if condition".into()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        _ => unreachable!("Only operators with a precedence are applied"),
+    })
+}
+
+/// Parses a decimal, hex or octal integer, with an optional `u` suffix
+fn parse_integer(text: &str) -> EvalResult {
+    let digits = text.trim_end_matches(['u', 'U']);
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    parsed.map_err(|_| format!("Invalid integer {} in #if condition", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str, options: &PreprocessOptions) -> String {
+        preprocess(source, options).unwrap().source
+    }
+
+    #[test]
+    fn test_defines() {
+        let source = "#define N 64\n#define COUNT (N * 2)\nfloat values[COUNT]; // N\nint N_X;";
+        let output = run(source, &PreprocessOptions::new());
+        assert_eq!(output, "\n\nfloat values[(64 * 2)]; // N\nint N_X;\n");
+
+        let options = PreprocessOptions::new().define("N", "8");
+        assert_eq!(run("float values[N];", &options), "float values[8];\n");
+
+        // Function-like macros are left as they are, but still count as defined
+        let source = "#define SQUARE(x) ((x) * (x))\n#ifdef SQUARE\nfloat a = SQUARE(N);\n#endif";
+        assert_eq!(run(source, &options), "\n\nfloat a = SQUARE(8);\n\n");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let source = "
+#if defined(FANCY) && LEVEL >= 2
+vec4 fancy;
+#elif LEVEL == 1 || (1 << 2) != 4
+vec3 plain;
+#else
+float fallback;
+#endif
+#ifndef FANCY
+int unfancy;
+#endif";
+        let options = PreprocessOptions::new().define("FANCY", "").define("LEVEL", "2");
+        let output = run(source, &options);
+        assert!(output.contains("vec4 fancy;"));
+        assert!(!output.contains("plain") && !output.contains("fallback"));
+        assert!(!output.contains("unfancy"));
+
+        let output = run(source, &PreprocessOptions::new().define("LEVEL", "1"));
+        assert!(output.contains("vec3 plain;") && output.contains("int unfancy;"));
+
+        let output = run(source, &PreprocessOptions::new());
+        assert!(output.contains("float fallback;"));
+    }
+
+    #[test]
+    fn test_include() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../shader_examples/particle_forces.comp");
+        let output = preprocess_file(path, &PreprocessOptions::new()).unwrap();
+        assert!(output.source.starts_with("#version 450\n"));
+
+        // The struct comes from the included file, and the line map says so
+        let line = output
+            .source
+            .lines()
+            .position(|l| l.starts_with("struct Particle"))
+            .unwrap();
+        let origin = output.origin(line + 1).unwrap();
+        assert!(origin.file.as_ref().unwrap().ends_with("particle_base.comp"));
+        assert_eq!(origin.line, 3);
+
+        let missing = preprocess("#include \"missing.comp\"", &PreprocessOptions::new());
+        assert!(matches!(missing, Err(crate::Error::Preprocess { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_errors() {
+        let options = PreprocessOptions::new();
+        let unterminated = preprocess("#ifdef A\nfloat a;", &options);
+        assert!(matches!(unterminated, Err(crate::Error::Preprocess { .. })));
+        let function_like = "#define SQUARE(x) ((x) * (x))\n#if SQUARE(2) > 1\n#endif";
+        let function_like = preprocess(function_like, &options);
+        assert!(matches!(function_like, Err(crate::Error::Preprocess { line: 2, .. })));
+        let error = preprocess("#if 1\n#error Unsupported\n#endif", &options);
+        assert!(matches!(error, Err(crate::Error::Preprocess { line: 2, .. })));
+    }
+}
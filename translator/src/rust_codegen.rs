@@ -0,0 +1,530 @@
+use crate::abstract_data::*;
+use crate::c_layout::explicit_offsets_c_mapped;
+use crate::glsl_layout::*;
+use crate::joint_layout::mirror_layout_c_mapped;
+use crate::type_map::TypeMap;
+use crate::Result;
+
+/// Settings for the generated Rust structs
+#[derive(Clone, Debug)]
+pub struct CodegenOptions {
+    /// Traits to derive on every generated struct, in order
+    pub derives: Vec<String>,
+    /// Rule the layout was computed under, which decides how arrays and matrices are widened
+    pub rule: LayoutRule,
+    /// Whether to follow each struct with compile-time checks of its size and field offsets
+    pub assertions: bool,
+    /// Whether to derive `bytemuck::Pod` and `bytemuck::Zeroable`, so the struct can be cast to bytes
+    pub bytemuck: bool,
+    /// Math library types to use in place of plain arrays
+    pub type_map: TypeMap,
+}
+
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        Self {
+            derives: vec!["Default".into(), "Copy".into(), "Clone".into()],
+            rule: LayoutRule::default(),
+            assertions: true,
+            bytemuck: false,
+            type_map: TypeMap::default(),
+        }
+    }
+}
+
+/// Generates a `#[repr(C)]` struct named `name` for a layout, preceded by the structs its fields refer to
+/// Every gap becomes a private `_padN: [u8; N]` field, so the Rust struct lands on the same offsets
+/// The layout should come from the Rust side of a `JointLayout` solved with the same type map
+/// The code is run through rustfmt when it's installed, which wraps lines holding long names
+pub fn generate_rust(name: &str, fgs: &[FieldGap], options: &CodegenOptions) -> Result<String> {
+    let mut nested = Vec::new();
+    for fg in fgs {
+        if let FieldGap::Field(f, _) = fg {
+            collect_structs(&f.ty, &mut nested);
+        }
+    }
+
+    let mut code = String::new();
+    for s in &nested {
+        let glsl = naive_layout_glsl_only(&s.fields, options.rule);
+        let layout = mirror_layout_c_mapped(&glsl, &options.type_map)?;
+        code.push_str(&generate_struct_unformatted(&s.name, &layout, options)?);
+        code.push('\n');
+    }
+    code.push_str(&generate_struct_unformatted(name, fgs, options)?);
+    Ok(rustfmt(code))
+}
+
+/// Generates a single `#[repr(C)]` struct, assuming any struct it refers to is defined elsewhere
+pub fn generate_struct(
+    name: &str,
+    fgs: &[FieldGap],
+    options: &CodegenOptions,
+) -> Result<String> {
+    generate_struct_unformatted(name, fgs, options).map(rustfmt)
+}
+
+/// Like `generate_struct`, leaving lines too long for rustfmt unwrapped
+fn generate_struct_unformatted(
+    name: &str,
+    fgs: &[FieldGap],
+    options: &CodegenOptions,
+) -> Result<String> {
+    let mut derives = options.derives.clone();
+    // A struct ending in a slice is unsized, so it can't be Copy, Clone or Default
+    if ends_in_runtime_array_layout(fgs) {
+        derives.clear();
+    }
+    if options.bytemuck {
+        check_no_implicit_padding(name, fgs, &options.type_map)?;
+        // Pod requires Copy, which in turn requires Clone
+        for derive in ["Copy", "Clone", "bytemuck::Pod", "bytemuck::Zeroable"] {
+            if !derives.iter().any(|d| d == derive) {
+                derives.push(derive.into());
+            }
+        }
+    }
+
+    // Each field as (name, Rust type, whether it is public)
+    let mut members = Vec::new();
+    let pad_prefix = padding_prefix(fgs);
+    let mut pads = 0;
+    for fg in fgs {
+        match fg {
+            FieldGap::Field(f, _) => members.push((
+                rust_identifier(&f.name),
+                options.type_map.rust_type(&f.ty, options.rule),
+                true,
+            )),
+            FieldGap::Gap(g, _) => {
+                members.push((format!("{}{}", pad_prefix, pads), format!("[u8; {}]", g), false));
+                pads += 1;
+            }
+        }
+    }
+
+    // Arrays longer than 32 don't implement Default, so the struct gets a zero-filling impl instead
+    let manual_default = derives.iter().any(|d| d == "Default")
+        && members.iter().any(|(_, ty, _)| has_long_array(ty));
+    if manual_default {
+        derives.retain(|d| d != "Default");
+    }
+
+    let mut code = String::from("#[repr(C)]\n");
+    if !derives.is_empty() {
+        code.push_str(&format!("#[derive({})]\n", derives.join(", ")));
+    }
+
+    if fgs.is_empty() {
+        code.push_str(&format!("pub struct {} {{}}\n", name));
+        return Ok(code);
+    }
+
+    code.push_str(&format!("pub struct {} {{\n", name));
+    for (member, ty, public) in &members {
+        let visibility = if *public { "pub " } else { "" };
+        code.push_str(&format!("    {}{}: {},\n", visibility, member, ty));
+    }
+    code.push_str("}\n");
+
+    if manual_default {
+        code.push_str(&format!(
+            "\nimpl Default for {} {{\n    fn default() -> Self {{\n        Self {{\n",
+            name
+        ));
+        for (member, ty, _) in &members {
+            code.push_str(&format!("            {}: {},\n", member, zero_value(ty)));
+        }
+        code.push_str("        }\n    }\n}\n");
+    }
+
+    if options.assertions {
+        code.push_str(&generate_assertions(name, fgs));
+    }
+    Ok(code)
+}
+
+/// Pod types may not contain any byte the compiler pads in, so every gap must already be an explicit field
+fn check_no_implicit_padding(name: &str, fgs: &[FieldGap], map: &TypeMap) -> Result<()> {
+    // A dynamically sized struct can't be Pod at all
+    if ends_in_runtime_array_layout(fgs) {
+        return Err(crate::Error::UnsizedPod { name: name.into() });
+    }
+
+    let (offsets, size) = explicit_offsets_c_mapped(fgs, map);
+    let mut offset = 0;
+    for (fg, rust_offset) in fgs.iter().zip(offsets) {
+        if rust_offset != offset {
+            return Err(crate::Error::ImplicitPadding { name: name.into() });
+        }
+        offset += fg.size();
+    }
+    if size != offset {
+        return Err(crate::Error::ImplicitPadding { name: name.into() });
+    }
+    Ok(())
+}
+
+/// Generates `const` assertions which fail the build if the struct drifts from the computed layout
+fn generate_assertions(name: &str, fgs: &[FieldGap]) -> String {
+    // Neither the size nor field offsets of a dynamically sized struct can be checked at compile time
+    if ends_in_runtime_array_layout(fgs) {
+        return String::new();
+    }
+
+    let mut code = format!(
+        "\nconst _: () = assert!(std::mem::size_of::<{}>() == {});\n",
+        name,
+        layout_size(fgs)
+    );
+    let mut offset = 0;
+    for fg in fgs {
+        if let FieldGap::Field(f, _) = fg {
+            code.push_str(&format!(
+                "const _: () = assert!(std::mem::offset_of!({}, {}) == {});\n",
+                name,
+                rust_identifier(&f.name),
+                offset
+            ));
+        }
+        offset += fg.size();
+    }
+    code
+}
+
+/// Whether a Rust type contains an array too long for the standard library to implement `Default` on
+fn has_long_array(ty: &str) -> bool {
+    ty.split("; ").skip(1).any(|len| {
+        let digits = len.trim_end_matches(']');
+        digits.parse::<u64>().is_ok_and(|len| len > 32)
+    })
+}
+
+/// An expression for the all-zero value of a generated Rust type
+/// Scalars and arrays of them become literals; anything else is assumed to implement `Default`
+fn zero_value(ty: &str) -> String {
+    let array = ty
+        .strip_prefix('[')
+        .and_then(|ty| ty.strip_suffix(']'))
+        .and_then(|ty| ty.rsplit_once("; "));
+    if let Some((elem, len)) = array {
+        let elem = zero_value(elem);
+        return if elem.starts_with('[') || elem.starts_with('0') {
+            format!("[{}; {}]", elem, len)
+        } else {
+            format!("std::array::from_fn(|_| {})", elem)
+        };
+    }
+    match ty {
+        "f32" | "f64" => "0.0".into(),
+        "u8" | "i32" | "u32" => "0".into(),
+        _ => "Default::default()".into(),
+    }
+}
+
+/// Adds the structs a type refers to, innermost first and each only once
+fn collect_structs(ty: &AbstractType, structs: &mut Vec<AbstractStruct>) {
+    match ty {
+        AbstractType::Struct(s) => {
+            for f in &s.fields {
+                collect_structs(&f.ty, structs);
+            }
+            if !structs.iter().any(|other| other.name == s.name) {
+                structs.push(s.clone());
+            }
+        }
+        AbstractType::Array(elem, _) | AbstractType::RuntimeArray(elem) => {
+            collect_structs(elem, structs)
+        }
+        _ => (),
+    }
+}
+
+/// Padding fields are called `_padN`, with more leading underscores if a field's name could clash
+fn padding_prefix(fgs: &[FieldGap]) -> String {
+    let mut prefix = String::from("_pad");
+    while fgs
+        .iter()
+        .any(|fg| matches!(fg, FieldGap::Field(f, _) if f.name.starts_with(&prefix)))
+    {
+        prefix.insert(0, '_');
+    }
+    prefix
+}
+
+/// Formats generated code with rustfmt, or leaves it as it is if rustfmt can't be run
+fn rustfmt(code: String) -> String {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let child = Command::new("rustfmt")
+        .args(["--edition", "2021", "--emit", "stdout", "--quiet"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return code,
+    };
+    let written = child
+        .stdin
+        .take()
+        .is_some_and(|mut stdin| stdin.write_all(code.as_bytes()).is_ok());
+    match child.wait_with_output() {
+        Ok(output) if written && output.status.success() => {
+            String::from_utf8(output.stdout).unwrap_or(code)
+        }
+        _ => code,
+    }
+}
+
+/// Name of the Rust field mirroring a GLSL one, without any `r#`
+/// Keywords which can't be raw identifiers get an underscore appended instead
+pub(crate) fn rust_field_name(name: &str) -> String {
+    match name {
+        "crate" | "self" | "Self" | "super" => format!("{}_", name),
+        _ => name.to_string(),
+    }
+}
+
+/// GLSL identifiers which are reserved in Rust get written as raw identifiers, where allowed
+fn rust_identifier(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe",
+        "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        rust_field_name(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joint_layout::solve_joint_layout;
+    use crate::layout_check::check_rust_struct;
+
+    /// Builds generated code as a library, failing with rustc's errors if it doesn't compile
+    fn assert_compiles(name: &str, code: &str) {
+        let dir = std::env::temp_dir().join(format!("struct_translator_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.rs", name));
+        std::fs::write(&path, code).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+        let output = std::process::Command::new(rustc)
+            .args(["--crate-type", "lib", "--edition", "2021", "-A", "warnings", "--out-dir"])
+            .arg(&dir)
+            .arg(&path)
+            .output()
+            .unwrap();
+        let errors = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}\n{}", code, errors);
+    }
+
+    #[test]
+    fn test_readme_vertex() {
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec3),
+        ];
+        let joint = solve_joint_layout(&fields, LayoutRule::Std140).unwrap();
+        let code = generate_rust("Vertex", &joint.rust, &CodegenOptions::default()).unwrap();
+        assert_eq!(
+            code,
+            "#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    _pad0: [u8; 4],
+    pub color: [f32; 3],
+    _pad1: [u8; 4],
+}
+
+const _: () = assert!(std::mem::size_of::<Vertex>() == 32);
+const _: () = assert!(std::mem::offset_of!(Vertex, position) == 0);
+const _: () = assert!(std::mem::offset_of!(Vertex, color) == 16);
+"
+        );
+    }
+
+    #[test]
+    fn test_nested_structs_and_keywords() {
+        let light = AbstractStruct {
+            name: "Light".into(),
+            fields: vec![AbstractField::new("type", AbstractType::UInt)],
+        };
+        let fields = [
+            AbstractField::new(
+                "lights",
+                AbstractType::Array(Box::new(AbstractType::Struct(light)), 2),
+            ),
+            AbstractField::new("count", AbstractType::UInt),
+        ];
+        let options = CodegenOptions {
+            derives: vec![],
+            rule: LayoutRule::Std430,
+            assertions: false,
+            ..Default::default()
+        };
+        let layout = naive_layout_glsl_only(&fields, options.rule);
+        let code = generate_rust("Scene", &layout, &options).unwrap();
+        assert_eq!(
+            code,
+            "#[repr(C)]
+pub struct Light {
+    pub r#type: u32,
+}
+
+#[repr(C)]
+pub struct Scene {
+    pub lights: [Light; 2],
+    pub count: u32,
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let fields = [
+            AbstractField::new("crate", AbstractType::Float),
+            AbstractField::new("self", AbstractType::Vec3),
+            AbstractField::new("Self", AbstractType::UInt),
+            AbstractField::new("super", AbstractType::UInt),
+            AbstractField::new("type", AbstractType::UInt),
+            AbstractField::new("_pad0", AbstractType::Vec4),
+        ];
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        let rust = mirror_layout_c_mapped(&layout, &TypeMap::default()).unwrap();
+        let code = generate_rust("Reserved", &rust, &CodegenOptions::default()).unwrap();
+        assert!(code.contains("pub crate_: f32,"));
+        assert!(code.contains("pub r#type: u32,"));
+        assert!(code.contains("pub _pad0: [f32; 4],"));
+        assert!(code.contains("__pad0: [u8; 12],"));
+        assert_compiles("reserved_names", &code);
+
+        let mismatches = check_rust_struct(&code, "Reserved", &layout, &TypeMap::default());
+        assert_eq!(mismatches.unwrap(), []);
+    }
+
+    #[test]
+    fn test_long_names_are_wrapped() {
+        let name = "AVeryLongStructNameWhichPushesAssertionsOverTheLineLimit";
+        let fields = [
+            AbstractField::new("a_field_name_long_enough_to_need_wrapping", AbstractType::Float),
+            AbstractField::new("weights", AbstractType::Array(Box::new(AbstractType::Float), 40)),
+        ];
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std140);
+        let rust = mirror_layout_c_mapped(&layout, &TypeMap::default()).unwrap();
+        let code = generate_rust(name, &rust, &CodegenOptions::default()).unwrap();
+        assert!(code.lines().all(|line| line.len() <= 100), "{}", code);
+        assert!(code.contains("const _: () = assert!(\n"));
+        assert_compiles("long_names", &code);
+    }
+
+    #[test]
+    fn test_runtime_array_has_no_derives() {
+        let elems = AbstractType::RuntimeArray(Box::new(AbstractType::Vec4));
+        let fields = [
+            AbstractField::new("count", AbstractType::UInt),
+            AbstractField::new("elems", elems),
+        ];
+        let layout = naive_layout_glsl_only(&fields, LayoutRule::Std430);
+        let code = generate_rust("Buffer", &layout, &CodegenOptions::default()).unwrap();
+        assert!(code.starts_with("#[repr(C)]\npub struct Buffer {"));
+        assert!(code.contains("pub elems: [[f32; 4]],"));
+    }
+
+    #[test]
+    fn test_long_array_default() {
+        let weights = AbstractType::Array(Box::new(AbstractType::Float), 64);
+        let fields = [
+            AbstractField::new("weights", weights),
+            AbstractField::new("tint", AbstractType::Vec3),
+        ];
+        let options = CodegenOptions {
+            assertions: false,
+            ..Default::default()
+        };
+        let layout = naive_layout_glsl_only(&fields, options.rule);
+        let rust = mirror_layout_c_mapped(&layout, &options.type_map).unwrap();
+        let code = generate_rust("Globals", &rust, &options).unwrap();
+        assert_eq!(
+            code,
+            "#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Globals {
+    pub weights: [[f32; 4]; 64],
+    pub tint: [f32; 3],
+    _pad0: [u8; 4],
+}
+
+impl Default for Globals {
+    fn default() -> Self {
+        Self {
+            weights: [[0.0; 4]; 64],
+            tint: [0.0; 3],
+            _pad0: [0; 4],
+        }
+    }
+}
+"
+        );
+        assert_eq!(zero_value("[Light; 40]"), "std::array::from_fn(|_| Default::default())");
+    }
+
+    #[test]
+    fn test_bytemuck() {
+        let options = CodegenOptions {
+            derives: vec![],
+            bytemuck: true,
+            ..Default::default()
+        };
+
+        let fields = [
+            AbstractField::new("position", AbstractType::Vec3),
+            AbstractField::new("color", AbstractType::Vec3),
+        ];
+        let joint = solve_joint_layout(&fields, LayoutRule::Std140).unwrap();
+        let code = generate_struct("Vertex", &joint.rust, &options).unwrap();
+        assert!(code.contains("#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]"));
+
+        // Without a padding field, rustc would pad before the double itself
+        let layout = [
+            FieldGap::Field(AbstractField::new("mass", AbstractType::Float), 4),
+            FieldGap::Field(AbstractField::new("charge", AbstractType::Double), 8),
+        ];
+        let result = generate_struct("Particle", &layout, &options);
+        assert!(matches!(result, Err(crate::Error::ImplicitPadding { .. })));
+    }
+
+    #[test]
+    fn test_type_map() {
+        let mat4 = AbstractType::Matrix(Matrix {
+            columns: 4,
+            rows: 4,
+            double: false,
+            row_major: false,
+        });
+        let fields = [
+            AbstractField::new("model", mat4),
+            AbstractField::new("tint", AbstractType::Vec3),
+        ];
+        let options = CodegenOptions {
+            derives: vec![],
+            assertions: false,
+            type_map: TypeMap::glam(),
+            ..Default::default()
+        };
+        let layout = naive_layout_glsl_only(&fields, options.rule);
+        let rust = mirror_layout_c_mapped(&layout, &options.type_map).unwrap();
+        let code = generate_rust("Instance", &rust, &options).unwrap();
+        assert!(code.contains("pub model: glam::Mat4,"));
+        assert!(code.contains("pub tint: glam::Vec3,"));
+    }
+}
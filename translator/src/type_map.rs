@@ -0,0 +1,205 @@
+use crate::abstract_data::*;
+use crate::glsl_layout::{round_up, LayoutRule};
+
+/// A Rust type standing in for a GLSL type, along with its layout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedType {
+    /// Path the generated code refers to the type by, e.g. `glam::Vec3A`
+    pub path: String,
+    pub size: u64,
+    pub align: u64,
+}
+
+/// Maps GLSL types onto types from a math library, in place of plain arrays like `[f32; 3]`
+/// Only exact matches are mapped, e.g. a `row_major` mat4 isn't covered by an entry for a column major one
+#[derive(Clone, Debug, Default)]
+pub struct TypeMap {
+    entries: Vec<(AbstractType, MappedType)>,
+}
+
+impl TypeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `ty` onto the Rust type at `path`, replacing any earlier mapping of it
+    pub fn insert(mut self, ty: AbstractType, path: &str, size: u64, align: u64) -> Self {
+        self.entries.retain(|(other, _)| *other != ty);
+        self.entries.push((
+            ty,
+            MappedType {
+                path: path.into(),
+                size,
+                align,
+            },
+        ));
+        self
+    }
+
+    pub fn get(&self, ty: &AbstractType) -> Option<&MappedType> {
+        self.entries
+            .iter()
+            .find(|(other, _)| other == ty)
+            .map(|(_, mapped)| mapped)
+    }
+
+    /// The mapped type at a Rust path, written out in full or as the name it's imported under
+    pub fn find_path(&self, path: &str) -> Option<&MappedType> {
+        let path: String = path.chars().filter(|c| !c.is_whitespace()).collect();
+        let path = path.trim_start_matches("::");
+        let suffix = format!("::{}", path);
+        self.entries
+            .iter()
+            .map(|(_, mapped)| mapped)
+            .find(|mapped| mapped.path == path || mapped.path.ends_with(&suffix))
+    }
+
+    /// Name of the Rust type mirroring `ty`, falling back to arrays for unmapped types
+    pub fn rust_type(&self, ty: &AbstractType, rule: LayoutRule) -> String {
+        match self.get(ty) {
+            Some(mapped) => mapped.path.clone(),
+            None => ty.rust_type(rule),
+        }
+    }
+
+    /// Alignment of the Rust type mirroring `ty` under `#[repr(C)]`
+    /// Nested structs get generated with mapped types of their own, so they're aligned like those
+    pub fn align_c(&self, ty: &AbstractType) -> u64 {
+        if let Some(mapped) = self.get(ty) {
+            return mapped.align;
+        }
+        match ty {
+            AbstractType::Struct(s) => {
+                let aligns = s.fields.iter().map(|f| self.align_c(&f.ty));
+                aligns.max().unwrap_or(1)
+            }
+            AbstractType::Array(elem, _) | AbstractType::RuntimeArray(elem)
+                if holds_struct(elem) =>
+            {
+                self.align_c(elem)
+            }
+            _ => ty.align_c(),
+        }
+    }
+
+    /// Size of the Rust type mirroring `ty` under `#[repr(C)]`, when laid out by `rule` in GLSL
+    /// Nested structs are padded out to their GLSL size, but may still be rounded up to a larger
+    /// alignment of a mapped type inside them
+    pub fn size_c(&self, ty: &AbstractType, rule: LayoutRule) -> u64 {
+        if let Some(mapped) = self.get(ty) {
+            return mapped.size;
+        }
+        match ty {
+            AbstractType::Struct(_) => round_up(ty.size_gl(rule), self.align_c(ty)),
+            AbstractType::Array(elem, len) if holds_struct(elem) => len * self.size_c(elem, rule),
+            _ => ty.size_gl(rule),
+        }
+    }
+
+    /// glam's types, assuming SIMD is enabled (so `Vec4` and `Mat4` are 16 byte aligned)
+    /// vec3 maps to the unaligned `Vec3`, since `Vec3A` can't be followed by a float in the same 16 bytes
+    pub fn glam() -> Self {
+        Self::new()
+            .insert(AbstractType::Vec2, "glam::Vec2", 8, 4)
+            .insert(AbstractType::Vec3, "glam::Vec3", 12, 4)
+            .insert(AbstractType::Vec4, "glam::Vec4", 16, 16)
+            .insert(AbstractType::IVec2, "glam::IVec2", 8, 4)
+            .insert(AbstractType::IVec3, "glam::IVec3", 12, 4)
+            .insert(AbstractType::IVec4, "glam::IVec4", 16, 4)
+            .insert(AbstractType::UVec2, "glam::UVec2", 8, 4)
+            .insert(AbstractType::UVec3, "glam::UVec3", 12, 4)
+            .insert(AbstractType::UVec4, "glam::UVec4", 16, 4)
+            .insert(AbstractType::DVec2, "glam::DVec2", 16, 8)
+            .insert(AbstractType::DVec3, "glam::DVec3", 24, 8)
+            .insert(AbstractType::DVec4, "glam::DVec4", 32, 8)
+            .insert(matrix(2, false), "glam::Mat2", 16, 16)
+            // Mat3A's columns are Vec3As, which matches the 16 byte column stride of std140 and std430
+            .insert(matrix(3, false), "glam::Mat3A", 48, 16)
+            .insert(matrix(4, false), "glam::Mat4", 64, 16)
+            .insert(matrix(2, true), "glam::DMat2", 32, 8)
+            .insert(matrix(3, true), "glam::DMat3", 72, 8)
+            .insert(matrix(4, true), "glam::DMat4", 128, 8)
+    }
+
+    /// nalgebra's statically sized vectors and matrices, none of which are over-aligned
+    pub fn nalgebra() -> Self {
+        Self::generic("nalgebra::Vector", "nalgebra::Matrix")
+    }
+
+    /// mint's interoperability types, using the column major matrices
+    pub fn mint() -> Self {
+        Self::generic("mint::Vector", "mint::ColumnMatrix")
+    }
+
+    /// cgmath's vectors and (column major) matrices
+    pub fn cgmath() -> Self {
+        Self::generic("cgmath::Vector", "cgmath::Matrix")
+    }
+
+    /// Libraries which name their types like `Vector3<f32>` and `Matrix4<f32>`, with the layout of plain arrays
+    fn generic(vector: &str, matrix_path: &str) -> Self {
+        let scalars = [
+            (AbstractType::Float, "f32"),
+            (AbstractType::Int, "i32"),
+            (AbstractType::UInt, "u32"),
+            (AbstractType::Double, "f64"),
+        ];
+        let mut map = Self::new();
+        for (scalar, name) in &scalars {
+            let size = scalar.component_size();
+            for n in 2..=4 {
+                let ty = AbstractType::vector(scalar.clone(), n);
+                let path = format!("{}{}<{}>", vector, n, name);
+                map = map.insert(ty, &path, size * n, size);
+            }
+        }
+        for (scalar, name) in [(AbstractType::Float, "f32"), (AbstractType::Double, "f64")] {
+            let double = scalar == AbstractType::Double;
+            let size = scalar.component_size();
+            for n in 2..=4 {
+                let path = format!("{}{}<{}>", matrix_path, n, name);
+                map = map.insert(matrix(n, double), &path, size * n * n, size);
+            }
+        }
+        map
+    }
+}
+
+/// A square, column major matrix
+/// Whether an array element is (or holds) a struct, rather than being written out as a plain array
+fn holds_struct(elem: &AbstractType) -> bool {
+    matches!(elem, AbstractType::Struct(_) | AbstractType::Array(..))
+}
+
+fn matrix(n: u64, double: bool) -> AbstractType {
+    AbstractType::Matrix(Matrix {
+        columns: n,
+        rows: n,
+        double,
+        row_major: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let glam = TypeMap::glam();
+        assert_eq!(glam.rust_type(&AbstractType::Vec3, LayoutRule::Std140), "glam::Vec3");
+        assert_eq!(glam.align_c(&matrix(4, false)), 16);
+
+        let glam = glam.insert(AbstractType::Vec3, "glam::Vec3A", 16, 16);
+        assert_eq!(glam.get(&AbstractType::Vec3).unwrap().size, 16);
+
+        let nalgebra = TypeMap::nalgebra();
+        let vec3 = nalgebra.get(&AbstractType::Vec3).unwrap();
+        assert_eq!(vec3.path, "nalgebra::Vector3<f32>");
+        assert_eq!(vec3.align, 4);
+
+        // Unmapped types fall back to arrays
+        let bvec2 = AbstractType::BVec2;
+        assert_eq!(TypeMap::mint().rust_type(&bvec2, LayoutRule::Std140), "[u32; 2]");
+    }
+}